@@ -1,7 +1,17 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
 use crate::core::{
     config::{generate_completion, Config},
     error::FResult,
-    transform::{Context, ContextIter, ExitCodes},
+    transform::{Context, ContextIter, ExecRes, ExitCodes},
 };
 
 use log::{error, trace, LevelFilter};
@@ -30,30 +40,99 @@ pub fn init(cfg: &Config) -> FResult<ExitCodes> {
     }
 
     let mut output = cfg.output()?;
+    let ctx = ContextIter::from_cfg(cfg)?;
+
+    let replay = if let Some(path) = &cfg.replay_file {
+        Some(ctx.replay_file(path)?)
+    } else if let Some(iteration) = cfg.replay {
+        Some(ctx.replay(iteration, cfg.replay_seed)?)
+    } else {
+        None
+    };
+    if let Some((request, res)) = replay {
+        Context::output_replay(cfg, &mut output, &request, &res)?;
+        return Ok(res.exit_code);
+    }
+
+    let n_thread = cfg.n_thread.max(1);
+    let delay = Duration::from_millis(cfg.delay);
+    // the start of the next run of any worker
+    let next_start = Mutex::new(Instant::now());
+    let stop = AtomicBool::new(false);
+    let (tx, rx) = mpsc::channel();
+
+    std::thread::scope(|s| {
+        for worker in 0..n_thread {
+            let mut ctx = ctx.for_worker(worker, n_thread, cfg.worker_rand(worker, n_thread))?;
+            let tx = tx.clone();
+            let stop = &stop;
+            let next_start = &next_start;
+            s.spawn(move || {
+                while !stop.load(Ordering::Relaxed) && !ctx.is_done() {
+                    if !delay.is_zero() {
+                        wait_for_slot(next_start, delay);
+                    }
+                    let iteration = ctx.iteration();
+                    let Some(res) = ctx.next() else {
+                        break;
+                    };
+                    if tx.send((iteration, res)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        // only the workers may hold a sender, otherwise the writer never finishes
+        drop(tx);
+
+        let res = write_ordered(cfg, &mut output, rx);
+        stop.store(true, Ordering::Relaxed);
+        res
+    })
+}
+
+/// Reserve the next start time shared by all workers and sleep until it is reached.
+/// Runs of all workers start at least delay apart
+fn wait_for_slot(next_start: &Mutex<Instant>, delay: Duration) {
+    let start = {
+        let mut next = next_start.lock().unwrap_or_else(|x| x.into_inner());
+        let start = (*next).max(Instant::now());
+        *next = start + delay;
+        start
+    };
+    let wait = start.saturating_duration_since(Instant::now());
+    trace!("Sleeping for {} ms", wait.as_millis());
+    std::thread::sleep(wait);
+}
+
+/// Collects the results of all workers and writes them in iteration order.
+/// Returns the aggregated exit code of all runs
+fn write_ordered(
+    cfg: &Config,
+    output: &mut dyn std::io::Write,
+    rx: Receiver<(u32, FResult<ExecRes>)>,
+) -> FResult<ExitCodes> {
     let mut overall_exit_code = ExitCodes::Success;
-    let mut ctx = ContextIter::from_cfg(cfg)?;
-    let delay = std::time::Duration::from_millis(cfg.delay);
+    let mut pending = BTreeMap::new();
+    let mut next = 0;
 
-    // TODO a new ctx for each thread and run them all
-    // and wait for all of them to finish
-    for _ in 0..cfg.n_thread {
-        ctx.try_for_each(|x| {
+    for (iteration, x) in rx {
+        pending.insert(iteration, x);
+        while let Some(x) = pending.remove(&next) {
+            next += 1;
             if let Err(x) = &x {
                 error!("{:?}", x);
                 overall_exit_code = ExitCodes::RunnerFailed;
                 if cfg.no_fail_on_err {
-                    return Ok(());
+                    continue;
                 }
             }
             let x = x?;
             if x.exit_code.is_failure() {
                 overall_exit_code = x.exit_code;
             }
-            let res = Context::output(cfg, &mut output, &x.out, &x.fmt);
-            trace!("Sleeping for {} ms", delay.as_millis());
-            std::thread::sleep(delay);
-            res
-        })?;
+            Context::output(cfg, output, &x)?;
+        }
     }
     Ok(overall_exit_code)
 }
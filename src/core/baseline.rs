@@ -0,0 +1,258 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::info;
+
+use super::runner::RunRes;
+
+/// Scales the median absolute deviation to the standard deviation of a normal distribution
+const MAD_SCALE: f64 = 1.4826;
+
+/// Smallest sigma that is used to compare durations.
+/// Fast targets often report identical durations which would turn every jitter into an outlier
+const MIN_SIGMA: Duration = Duration::from_millis(1);
+
+/// Median and sigma of durations
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TimingStats {
+    pub median: Duration,
+    pub sigma: Duration,
+}
+
+impl TimingStats {
+    /// Robust statistics of the samples, the sigma is derived from the median absolute deviation
+    pub fn new(samples: &[Duration]) -> Option<Self> {
+        let mid = median(samples.to_vec())?;
+        let deviations: Vec<Duration> = samples.iter().map(|x| x.abs_diff(mid)).collect();
+        let mad = median(deviations)?;
+        Some(Self {
+            median: mid,
+            sigma: mad.mul_f64(MAD_SCALE).max(MIN_SIGMA),
+        })
+    }
+
+    /// true if the duration is more than n_sigma away from the median
+    pub fn deviates(&self, duration: Duration, n_sigma: f64) -> bool {
+        duration.abs_diff(self.median) > self.sigma.mul_f64(n_sigma)
+    }
+}
+
+fn median(mut samples: Vec<Duration>) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+    samples.sort();
+    let mid = samples.len() / 2;
+    if samples.len().is_multiple_of(2) {
+        Some((samples[mid - 1] + samples[mid]) / 2)
+    } else {
+        Some(samples[mid])
+    }
+}
+
+/// Learns the normal duration of a run from n_sample calibration runs.
+/// The baseline is shared by all workers
+#[derive(Clone, Default, Debug)]
+pub struct TimingBaseline {
+    n_sample: usize,
+    stats: Arc<Mutex<Option<TimingStats>>>,
+}
+
+impl TimingBaseline {
+    pub fn new(n_sample: usize) -> Self {
+        Self {
+            n_sample,
+            stats: Default::default(),
+        }
+    }
+
+    /// The amount of calibration runs the baseline is learned from
+    pub fn n_sample(&self) -> usize {
+        self.n_sample.max(1)
+    }
+
+    pub fn calibrate(&self, samples: &[Duration]) {
+        let stats = TimingStats::new(samples);
+        info!("Calibrated timing baseline {:?}", stats);
+        if let Ok(mut x) = self.stats.lock() {
+            *x = stats;
+        }
+    }
+
+    /// The statistics once the baseline was calibrated
+    pub fn stats(&self) -> Option<TimingStats> {
+        self.stats.lock().ok().and_then(|x| *x)
+    }
+}
+
+/// Properties of a response that are compared with the baseline
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct ResponseFeatures {
+    /// The http status or the exit code of other runners
+    pub status: Option<i32>,
+    pub len: usize,
+    pub words: usize,
+    pub lines: usize,
+    /// Hash of the body with numbers and whitespace normalized
+    pub hash: u64,
+}
+
+impl ResponseFeatures {
+    /// Features of the http body or the output of other runners
+    pub fn new(res: &RunRes) -> Self {
+        let (status, body) = match &res.http {
+            Some(http) => (Some(http.status as i32), &http.body),
+            None => (res.exit_code, &res.out),
+        };
+        let text = String::from_utf8_lossy(body);
+        Self {
+            status,
+            len: body.len(),
+            words: text.split_whitespace().count(),
+            lines: text.lines().count(),
+            hash: normalized_hash(&text),
+        }
+    }
+}
+
+/// Hash that ignores changing numbers such as timestamps and ids as well as whitespace
+fn normalized_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    let mut last = None;
+    for c in text.chars() {
+        let c = if c.is_ascii_digit() {
+            '0'
+        } else if c.is_whitespace() {
+            ' '
+        } else {
+            c
+        };
+        if (c == '0' || c == ' ') && last == Some(c) {
+            continue;
+        }
+        c.hash(&mut hasher);
+        last = Some(c);
+    }
+    hasher.finish()
+}
+
+/// The normal responses observed during calibration
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Calibration {
+    pub statuses: Vec<Option<i32>>,
+    pub hashes: Vec<u64>,
+    pub len: RangeInclusive<usize>,
+    pub words: RangeInclusive<usize>,
+    pub lines: RangeInclusive<usize>,
+}
+
+impl Calibration {
+    pub fn new(samples: &[ResponseFeatures]) -> Option<Self> {
+        let range = |f: fn(&ResponseFeatures) -> usize| {
+            let min = samples.iter().map(f).min()?;
+            let max = samples.iter().map(f).max()?;
+            Some(min..=max)
+        };
+        Some(Self {
+            statuses: samples.iter().map(|x| x.status).collect(),
+            hashes: samples.iter().map(|x| x.hash).collect(),
+            len: range(|x| x.len)?,
+            words: range(|x| x.words)?,
+            lines: range(|x| x.lines)?,
+        })
+    }
+
+    /// A response is an anomaly if its status was never observed or if its body differs
+    /// from all normal bodies and its shape is outside of the observed ranges
+    pub fn is_anomaly(&self, res: &ResponseFeatures) -> bool {
+        if !self.statuses.contains(&res.status) {
+            return true;
+        }
+        !self.hashes.contains(&res.hash)
+            && (!self.len.contains(&res.len)
+                || !self.words.contains(&res.words)
+                || !self.lines.contains(&res.lines))
+    }
+}
+
+/// Learns the normal response during the calibration phase.
+/// The baseline is shared by all workers
+#[derive(Clone, Default, Debug)]
+pub struct ResponseBaseline {
+    calibration: Arc<Mutex<Option<Calibration>>>,
+}
+
+impl ResponseBaseline {
+    pub fn calibrate(&self, samples: &[RunRes]) {
+        let features: Vec<ResponseFeatures> = samples.iter().map(ResponseFeatures::new).collect();
+        let calibration = Calibration::new(&features);
+        info!("Calibrated response baseline {:?}", calibration);
+        if let Ok(mut x) = self.calibration.lock() {
+            *x = calibration;
+        }
+    }
+
+    /// true if the result deviates from the baseline. Nothing deviates before calibration
+    pub fn is_anomaly(&self, res: &RunRes) -> bool {
+        self.calibration
+            .lock()
+            .ok()
+            .and_then(|x| {
+                x.as_ref()
+                    .map(|x| x.is_anomaly(&ResponseFeatures::new(res)))
+            })
+            .unwrap_or(false)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::core::runner::RunRes;
+
+    use super::{ResponseBaseline, TimingBaseline, TimingStats};
+
+    fn ms(x: u64) -> Duration {
+        Duration::from_millis(x)
+    }
+
+    #[test]
+    fn timing() {
+        let stats = TimingStats::new(&[ms(10), ms(12), ms(11), ms(500), ms(9)]).unwrap();
+        assert_eq!(ms(11), stats.median);
+        assert!(!stats.deviates(ms(13), 3.0));
+        assert!(stats.deviates(ms(100), 3.0));
+
+        let baseline = TimingBaseline::new(3);
+        assert_eq!(None, baseline.stats());
+        baseline.calibrate(&[ms(10), ms(10), ms(10), ms(1000)]);
+        let stats = baseline.stats().unwrap();
+        assert_eq!(ms(10), stats.median);
+        // a constant baseline uses the minimum sigma
+        assert_eq!(ms(1), stats.sigma);
+    }
+
+    #[test]
+    fn anomalies() {
+        let res = |exit_code: i32, out: &str| RunRes::new(Some(exit_code), out.into());
+        let baseline = ResponseBaseline::default();
+        assert!(!baseline.is_anomaly(&res(1, "anything")));
+
+        baseline.calibrate(&[
+            res(0, "<p>not found: aaaa</p>\nrequest 12"),
+            res(0, "<p>not found: bbbbbbbb</p>\nrequest 13"),
+        ]);
+        // only numbers and whitespace changed
+        assert!(!baseline.is_anomaly(&res(0, "<p>not found: aaaa</p>\n\nrequest  9999")));
+        // a different reflected word with the same shape
+        assert!(!baseline.is_anomaly(&res(0, "<p>not found: cccccc</p>\nrequest 14")));
+        assert!(baseline.is_anomaly(&res(1, "<p>not found: aaaa</p>\nrequest 12")));
+        assert!(baseline.is_anomaly(&res(0, "<p>welcome admin</p>\n<p>settings</p>\nlogout")));
+    }
+}
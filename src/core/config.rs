@@ -1,6 +1,6 @@
 use std::{
     io::{BufReader, LineWriter, Read, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 
 #[cfg(feature = "cli")]
@@ -10,7 +10,17 @@ use clap_complete::{generate, Generator, Shell};
 use lazy_static::lazy_static;
 use log::debug;
 
-use super::{error::FResult, rand::Rand, transform::Word};
+use super::{
+    error::{Error, FResult},
+    mutate::Mutation,
+    rand::Rand,
+    reflect::Encoding,
+    runner::{server::ExecMode, socket::ConnErr, unix::UnixKind},
+    transform::{Target, TargetWords, Word},
+};
+
+/// Calibration words of different lengths so that reflected words change the response size
+const DEFAULT_CALIBRATION_WORDS: &[&str] = &["xq7kz", "calib4t1on", "m9d2owq8vtk3lzr0"];
 
 lazy_static! {
     pub static ref CFG: Config = Config::new();
@@ -24,6 +34,10 @@ pub enum RunnerKindConfig {
     Output,
     Http,
     Jwt,
+    File,
+    Tcp,
+    Udp,
+    Unix,
     #[default]
     None,
 }
@@ -37,6 +51,34 @@ pub enum SignatureConfig {
     None,
 }
 
+/// How words are selected for each target occurrence
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Strategy {
+    /// Select a random word for every occurrence
+    #[default]
+    Random,
+    /// Use the n-th word of each list in the n-th run until the longest list is exhausted
+    Sequential,
+    /// Try every combination of words for all occurrences
+    Cartesian,
+    /// Zip all lists and stop once the shortest list is exhausted
+    Pitchfork,
+}
+
+/// Output stream of a runner
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum Stream {
+    #[default]
+    Stdout,
+    Stderr,
+    /// Either stdout or stderr
+    Both,
+    /// The body of a http response. The entire output for other runners
+    Body,
+}
+
 // Http method
 // TODO implement more methods in the future, use curl as --exec for now if needed
 #[cfg_attr(feature = "cli", derive(ValueEnum))]
@@ -87,13 +129,214 @@ pub struct Config {
     #[cfg_attr(feature = "cli", clap(long, help = "Expected exit code"))]
     pub expect_exit_code: Vec<i32>,
 
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "STATUS",
+            help = "Expected http status code. Accepts codes (404), classes (5xx) and ranges (500-599)"
+        )
+    )]
+    pub expect_status: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "NAME[:REGEX]",
+            help = "Expect a http response header to be present. If a regex is supplied the value has to match it"
+        )
+    )]
+    pub expect_header: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Expected Content-Length of a http response. The body length is used if the header is missing"
+        )
+    )]
+    pub expect_content_length: Vec<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Parse the response body as json and check a JSONPath or pointer, e.g. '$.error.code == 500', '/user/role != \"admin\"', '$.items is array' or '$.count in 1..10'. A path without a check only has to exist"
+        )
+    )]
+    pub expect_json: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "MS",
+            help = "Expect runs that take longer than this"
+        )
+    )]
+    pub expect_slower_than: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "SIGMA",
+            help = "Expect runs whose duration deviates from the baseline median by more than this many standard deviations"
+        )
+    )]
+    pub expect_time_deviation: Option<f64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value_t = 10,
+            help = "Number of calibration runs the timing baseline is learned from"
+        )
+    )]
+    pub timing_baseline: usize,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Run the input with benign words before fuzzing and expect responses that differ from them in status, length, word count, line count or content"
+        )
+    )]
+    pub expect_anomaly: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Expect any of the substituted words to be reflected in the output"
+        )
+    )]
+    pub expect_reflection: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            help = "Encoding a reflected word may appear in. Defaults to all encodings"
+        )
+    )]
+    pub reflection_encoding: Vec<Encoding>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Benign word that replaces all targets during calibration. Defaults to a few random looking words"
+        )
+    )]
+    pub calibration_word: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Expect a boolean expression such as 'status>=500 && !contains(\"maintenance\") || stderr.regex(\"stack trace\")'. Supports &&, ||, !, parentheses, comparisons of status, exit_code, len, content_length and time (ms), and contains, equals, regex, header, json, conn_err, reflected, timeout and anomaly"
+        )
+    )]
+    pub expect_expr: Vec<String>,
+
+    #[cfg_attr(feature = "cli", clap(long, help = "Expect the runner to time out"))]
+    pub expect_timeout: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            help = "Expect this connection error from socket runners"
+        )
+    )]
+    pub expect_conn_err: Vec<ConnErr>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            default_value_t = Stream::Stdout,
+            help = "The output stream --expect, --contains, --expect-regex and --expect-len are applied to"
+        )
+    )]
+    pub expect_stream: Stream,
+
     #[cfg_attr(feature = "cli", clap(long, help = "Replace target for command args",
         default_value =crate::core::transform::DEFAULT_TARGET_WORD))]
     pub exec_target: String,
 
-    #[cfg_attr(feature = "cli", clap(long))]
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Kill the command and report a hang if it runs longer than this in ms"
+        )
+    )]
+    pub exec_timeout: Option<u32>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            default_value_t = ExecMode::Spawn,
+            help = "How the command is executed. Long lived modes do not support targets in the command args"
+        )
+    )]
+    pub exec_mode: ExecMode,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Replaced with the path of a file containing the input in command args. Selects the file runner",
+            default_value = crate::core::runner::file::DEFAULT_FILE_TARGET
+        )
+    )]
+    pub file_target: String,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Directory the file runner writes inputs to")
+    )]
+    pub file_dir: Option<PathBuf>,
+
+    #[cfg_attr(feature = "cli", clap(long, help = "Extension of input files"))]
+    pub file_ext: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Name of input files. {iteration}, {pid} and {hash} are replaced. Files are only kept if the result is expected, a crash or a hang"
+        )
+    )]
+    pub file_name: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Http url. When used with --http-request only the scheme and host are used"
+        )
+    )]
     pub url: Option<String>,
 
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Raw HTTP/1.1 request file that is used as the request template. Targets are replaced in the entire request. Requests without a body send the input as body. --header replaces headers of the same name"
+        )
+    )]
+    pub http_request: Option<PathBuf>,
+
     #[cfg_attr(
         feature = "cli",
         clap(long, help = "Specify a http header and value (header:value)")
@@ -112,6 +355,132 @@ pub struct Config {
     #[cfg_attr(feature = "cli", clap(long, help = "Http request timeout in ms"))]
     pub http_timeout: Option<u32>,
 
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Send http requests through this proxy")
+    )]
+    pub proxy: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Do not verify tls certificates and host names")
+    )]
+    pub insecure: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Prefer HTTP/2. Tls connections negotiate it via ALPN and fall back to HTTP/1.1. Without it requests use HTTP/1.1"
+        )
+    )]
+    pub http2: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Use HTTP/2 with prior knowledge, also on plain text connections. There is no fall back to HTTP/1.1"
+        )
+    )]
+    pub http2_prior_knowledge: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value_t = 0,
+            help = "Maximum amount of open http connections per host. 0 means no limit"
+        )
+    )]
+    pub max_connections_per_host: usize,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Keep cookies set by responses for all following requests"
+        )
+    )]
+    pub http_cookies: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Follow up to this many http redirects")
+    )]
+    pub max_redirects: Option<u32>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Raw HTTP/1.1 request that is sent once before fuzzing. Cookies it sets are kept"
+        )
+    )]
+    pub login_request: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Regex that extracts a token from the login response. The first capture group is used if there is one"
+        )
+    )]
+    pub login_token: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value = "Authorization: Bearer {token}",
+            help = "Header the login token is sent in. {token} is replaced with the token"
+        )
+    )]
+    pub login_header: String,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "HOST:PORT",
+            help = "Send the input to this address. Uses tcp unless --runner udp is selected"
+        )
+    )]
+    pub addr: Option<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Send the input to the unix domain socket at this path")
+    )]
+    pub unix_socket: Option<String>,
+
+    #[cfg_attr(feature = "cli", clap(long, value_enum, default_value_t = UnixKind::Stream))]
+    pub unix_kind: UnixKind,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Socket connect timeout and the time in ms a reply may be idle before it is complete. Udp collects replies for this long"
+        )
+    )]
+    pub socket_timeout: Option<u32>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Stop reading a socket reply after this many bytes")
+    )]
+    pub socket_max_read: Option<usize>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Close the write side of the socket after sending the input"
+        )
+    )]
+    pub socket_shutdown: bool,
+
     #[cfg_attr(feature = "cli", clap(long))]
     pub jwt_secret: Option<Word>,
 
@@ -144,15 +513,86 @@ pub struct Config {
     #[cfg_attr(feature = "cli", clap(long))]
     pub word: Vec<String>,
 
-    #[cfg_attr(feature = "cli", clap(long, short, default_value_t = 1))]
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "NAME=PATH",
+            help = "Add a named target that is replaced with words from its own word list"
+        )
+    )]
+    pub target_list: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_name = "NAME=WORD",
+            help = "Add a word to a named target's word list"
+        )
+    )]
+    pub target_word: Vec<String>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            short,
+            default_value_t = 1,
+            help = "Number of runs. Ignored by all strategies except random"
+        )
+    )]
     pub n_run: u32,
 
-    #[cfg_attr(feature = "cli", clap(long, default_value_t = 1))]
+    #[cfg_attr(feature = "cli", clap(long, value_enum, default_value_t = Strategy::Random))]
+    pub strategy: Strategy,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            value_enum,
+            help = "Mutate the input before targets are replaced. Each run picks from all supplied mutations"
+        )
+    )]
+    pub mutate: Vec<Mutation>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value_t = 1,
+            help = "Maximum amount of stacked mutations per run"
+        )
+    )]
+    pub n_mutation: u32,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Files that are spliced into the input by the splice mutation, in addition to the loaded corpus and inputs that found new coverage"
+        )
+    )]
+    pub splice_file: Vec<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            default_value_t = 1,
+            help = "Number of worker threads. The runs are split between all workers"
+        )
+    )]
     pub n_thread: u32,
 
     #[cfg_attr(
         feature = "cli",
-        clap(long, default_value_t = 0, help = "Delay between teach run in ms")
+        clap(
+            long,
+            default_value_t = 0,
+            help = "Delay between the start of two runs in ms. The delay applies to all workers together"
+        )
     )]
     pub delay: u64,
 
@@ -165,6 +605,33 @@ pub struct Config {
     #[cfg_attr(feature = "cli", clap(long))]
     pub seed: Option<u64>,
 
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Re-run a single iteration and print the full request and response. The iteration's seed is derived from --seed unless --replay-seed is set. Runs with --coverage can not be replayed"
+        )
+    )]
+    pub replay: Option<u32>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "The seed of the replayed iteration as printed in the output"
+        )
+    )]
+    pub replay_seed: Option<u64>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Re-run a finding saved in a corpus directory and print the full request and response. The input is sent as is, inputs saved only for their coverage are refused"
+        )
+    )]
+    pub replay_file: Option<PathBuf>,
+
     #[cfg_attr(feature = "cli", arg(short, long, action = clap::ArgAction::Count))]
     pub verbose: u8,
 
@@ -180,6 +647,21 @@ pub struct Config {
     #[cfg_attr(feature = "cli", arg(long, default_value_t = false))]
     pub no_color: bool,
 
+    #[cfg_attr(
+        feature = "cli",
+        arg(long, help = "Output the stderr of commands after their stdout")
+    )]
+    pub show_stderr: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        arg(
+            long,
+            help = "Output the duration of every run. Findings always show their duration"
+        )
+    )]
+    pub show_duration: bool,
+
     #[cfg_attr(feature = "cli", arg(long, default_value_t = false))]
     pub no_fail_on_err: bool,
 
@@ -195,6 +677,30 @@ pub struct Config {
     #[cfg_attr(feature = "cli", clap(long, help = "Disable runners"))]
     pub dry_run: bool,
 
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Pass an AFL-style coverage map to instrumented shell targets and keep inputs that hit new edges"
+        )
+    )]
+    pub coverage: bool,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(
+            long,
+            help = "Save inputs that are expected, crash, hang or found new coverage to this directory"
+        )
+    )]
+    pub corpus_dir: Option<PathBuf>,
+
+    #[cfg_attr(
+        feature = "cli",
+        clap(long, help = "Use all inputs in a corpus directory as seed inputs")
+    )]
+    pub load_corpus: Vec<PathBuf>,
+
     #[cfg_attr(feature = "cli", clap(long, value_name = "SHELL"))]
     #[cfg(feature = "cli")]
     pub completions: Option<Shell>,
@@ -236,10 +742,17 @@ impl Config {
     }
 
     pub fn rand(&self) -> Rand {
+        self.worker_rand(0, 1)
+    }
+
+    /// Rand for one of n worker threads.
+    /// Seeded runs derive a distinct but deterministic seed for each worker,
+    /// random files are split between the workers
+    pub fn worker_rand(&self, worker: u32, n_worker: u32) -> Rand {
         if let Some(seed) = self.seed {
-            Rand::from_seed(seed)
+            Rand::from_seed(seed.wrapping_add(worker as u64))
         } else if let Some(path) = &self.random_file {
-            Rand::from_path(path)
+            Rand::from_path_strided(path, worker as u64, n_worker as u64)
         } else {
             Rand::default()
         }
@@ -253,13 +766,7 @@ impl Config {
         }
 
         for path in &self.word_list {
-            let all = std::fs::read_to_string(path)?;
-            res.append(
-                &mut all
-                    .split(&self.word_list_term)
-                    .map(|x| x.to_owned().into_bytes())
-                    .collect(),
-            );
+            res.append(&mut self.read_word_list(path)?);
         }
 
         for path in &self.word_file {
@@ -275,6 +782,63 @@ impl Config {
         Ok(res)
     }
 
+    fn read_word_list(&self, path: &Path) -> FResult<Vec<Word>> {
+        let all = std::fs::read_to_string(path)?;
+        Ok(all
+            .split(&self.word_list_term)
+            .map(|x| x.to_owned().into_bytes())
+            .collect())
+    }
+
+    /// The default target followed by all named targets.
+    /// Named targets that are declared more than once share one word list
+    pub fn targets(&self) -> FResult<Vec<TargetWords>> {
+        let mut res = vec![TargetWords::new(
+            Target::Word(self.target.to_owned().into_bytes()),
+            self.words()?,
+        )];
+
+        fn named<'a>(res: &'a mut Vec<TargetWords>, name: &str) -> &'a mut TargetWords {
+            let target = Target::Word(name.to_owned().into_bytes());
+            if let Some(i) = res.iter().skip(1).position(|x| x.target == target) {
+                &mut res[i + 1]
+            } else {
+                res.push(TargetWords::new(target, vec![]));
+                res.last_mut().unwrap()
+            }
+        }
+
+        for list in &self.target_list {
+            let (name, path) = list.split_once('=').ok_or(Error::ArgError)?;
+            let mut words = self.read_word_list(Path::new(path))?;
+            named(&mut res, name).words.append(&mut words);
+        }
+
+        for word in &self.target_word {
+            let (name, word) = word.split_once('=').ok_or(Error::ArgError)?;
+            named(&mut res, name)
+                .words
+                .push(word.to_owned().into_bytes());
+        }
+
+        Ok(res)
+    }
+
+    /// Words that are used to learn the normal response
+    pub fn calibration_words(&self) -> Vec<Word> {
+        if self.calibration_word.is_empty() {
+            DEFAULT_CALIBRATION_WORDS
+                .iter()
+                .map(|x| x.as_bytes().to_vec())
+                .collect()
+        } else {
+            self.calibration_word
+                .iter()
+                .map(|x| x.as_bytes().to_vec())
+                .collect()
+        }
+    }
+
     // returns the command as well as args
     pub fn cmd(&self) -> FResult<Option<String>> {
         if let Some(exec) = &self.exec {
@@ -0,0 +1,209 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info};
+use sha2::{Digest, Sha256};
+
+use super::error::{Error, FResult};
+
+/// File extension of the metadata that is stored next to every corpus entry
+pub const META_EXTENSION: &str = "meta";
+
+/// Why an input was saved to the corpus
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum CorpusReason {
+    Expected,
+    Coverage,
+    Crash,
+    Hang,
+    /// A socket target refused or reset the connection
+    Connection,
+}
+
+impl CorpusReason {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "expected" => Some(Self::Expected),
+            "coverage" => Some(Self::Coverage),
+            "crash" => Some(Self::Crash),
+            "hang" => Some(Self::Hang),
+            "connection" => Some(Self::Connection),
+            _ => None,
+        }
+    }
+}
+
+/// Metadata of a saved input
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct CorpusMeta {
+    pub reasons: Vec<CorpusReason>,
+    pub seed: Option<u64>,
+    pub iteration: u32,
+    /// The seed of the iteration's rand, None for runs that read a random file
+    pub iteration_seed: Option<u64>,
+    /// The word indexes that were substituted into the runner's templates
+    pub template_words: Vec<usize>,
+    pub runner: String,
+    pub exit_code: Option<i32>,
+}
+
+impl CorpusMeta {
+    /// Serialize as key=value lines
+    pub fn write(&self, output: &mut dyn Write) -> FResult<()> {
+        let reasons: Vec<String> = self
+            .reasons
+            .iter()
+            .map(|x| format!("{x:?}").to_lowercase())
+            .collect();
+        writeln!(output, "reason={}", reasons.join(","))?;
+        if let Some(seed) = self.seed {
+            writeln!(output, "seed={seed}")?;
+        }
+        writeln!(output, "iteration={}", self.iteration)?;
+        if let Some(iteration_seed) = self.iteration_seed {
+            writeln!(output, "iteration_seed={iteration_seed}")?;
+        }
+        let template_words: Vec<String> =
+            self.template_words.iter().map(|x| x.to_string()).collect();
+        writeln!(output, "template_words={}", template_words.join(","))?;
+        writeln!(output, "runner={}", self.runner)?;
+        if let Some(exit_code) = self.exit_code {
+            writeln!(output, "exit_code={exit_code}")?;
+        }
+        Ok(())
+    }
+
+    /// Parse key=value lines. Unknown keys are ignored
+    pub fn parse(meta: &str) -> FResult<Self> {
+        fn number<T: std::str::FromStr>(value: &str) -> FResult<T> {
+            value.parse().map_err(|_| Error::ArgError)
+        }
+
+        let mut res = Self::default();
+        for (key, value) in meta.lines().filter_map(|x| x.split_once('=')) {
+            match key {
+                "reason" => {
+                    res.reasons = value
+                        .split(',')
+                        .filter_map(CorpusReason::from_name)
+                        .collect()
+                }
+                "seed" => res.seed = Some(number(value)?),
+                "iteration" => res.iteration = number(value)?,
+                "iteration_seed" => res.iteration_seed = Some(number(value)?),
+                "template_words" => {
+                    res.template_words = value
+                        .split(',')
+                        .filter(|x| !x.is_empty())
+                        .map(number)
+                        .try_collect()?
+                }
+                "runner" => res.runner = value.into(),
+                "exit_code" => res.exit_code = Some(number(value)?),
+                _ => {}
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// A directory of interesting inputs.
+/// Every input is stored in a file named after the hash of its content
+/// with a metadata sidecar file
+#[derive(Clone, Debug)]
+pub struct CorpusDir {
+    path: PathBuf,
+}
+
+impl CorpusDir {
+    /// Open a corpus directory and create it if it does not exist
+    pub fn new(path: &Path) -> FResult<Self> {
+        std::fs::create_dir_all(path)?;
+        Ok(Self { path: path.into() })
+    }
+
+    /// Hex encoded sha256 of the input
+    pub fn name(input: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(input))
+    }
+
+    /// Save an input and its metadata.
+    /// Inputs that are already in the corpus are not overwritten
+    pub fn save(&self, input: &[u8], meta: &CorpusMeta) -> FResult<PathBuf> {
+        let path = self.path.join(Self::name(input));
+        // workers may find the same input at the same time
+        let mut f = match std::fs::File::options()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(f) => f,
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => {
+                debug!("{:?} is already in the corpus", path);
+                return Ok(path);
+            }
+            Err(err) => return Err(err.into()),
+        };
+        f.write_all(input)?;
+        let mut f = std::fs::File::create(path.with_extension(META_EXTENSION))?;
+        meta.write(&mut f)?;
+
+        info!("Saved {:?} to corpus", path);
+        Ok(path)
+    }
+
+    /// Load all inputs from a corpus directory, metadata files are skipped
+    pub fn load(path: &Path) -> FResult<Vec<Vec<u8>>> {
+        let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+            .map(|x| x.map(|x| x.path()))
+            .try_collect()?;
+        entries.sort();
+
+        let mut res = Vec::new();
+        for entry in entries {
+            if entry.is_file() && entry.extension().and_then(|x| x.to_str()) != Some(META_EXTENSION)
+            {
+                res.push(std::fs::read(&entry)?);
+            }
+        }
+        debug!("Loaded {} inputs from {:?}", res.len(), path);
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CorpusDir, CorpusMeta, CorpusReason};
+
+    #[test]
+    fn save_and_load() {
+        let path = std::env::temp_dir().join(format!("oxifuzz-corpus-{}", std::process::id()));
+        let corpus = CorpusDir::new(&path).unwrap();
+        let meta = CorpusMeta {
+            reasons: vec![CorpusReason::Expected],
+            seed: Some(1),
+            iteration: 2,
+            iteration_seed: Some(3),
+            template_words: vec![4, 0],
+            runner: "output".into(),
+            exit_code: None,
+        };
+
+        let saved = corpus.save(b"finding", &meta).unwrap();
+        assert_eq!(saved, corpus.save(b"finding", &meta).unwrap());
+        assert_eq!(
+            "reason=expected\nseed=1\niteration=2\niteration_seed=3\ntemplate_words=4,0\nrunner=output\n",
+            std::fs::read_to_string(saved.with_extension("meta")).unwrap()
+        );
+        assert_eq!(
+            meta,
+            CorpusMeta::parse(&std::fs::read_to_string(saved.with_extension("meta")).unwrap())
+                .unwrap()
+        );
+        assert_eq!(vec![b"finding".to_vec()], CorpusDir::load(&path).unwrap());
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+}
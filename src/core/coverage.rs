@@ -0,0 +1,191 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use log::{debug, info};
+
+use super::{
+    error::{Error, FResult},
+    transform::Word,
+};
+
+/// Size of the AFL edge bitmap
+pub const MAP_SIZE: usize = 1 << 16;
+
+/// Environment variable the instrumented child reads the shared memory id from
+pub const SHM_ENV_VAR: &str = "__AFL_SHM_ID";
+
+/// A System V shared memory segment that holds the edge bitmap of a single child
+pub struct ShmMap {
+    id: i32,
+    ptr: *mut u8,
+}
+
+// the map is only ever written to by the child process of the worker that owns it
+unsafe impl Send for ShmMap {}
+unsafe impl Sync for ShmMap {}
+
+impl ShmMap {
+    pub fn new() -> FResult<Self> {
+        let id = unsafe {
+            libc::shmget(
+                libc::IPC_PRIVATE,
+                MAP_SIZE,
+                libc::IPC_CREAT | libc::IPC_EXCL | 0o600,
+            )
+        };
+        if id < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let ptr = unsafe { libc::shmat(id, std::ptr::null(), 0) };
+        if ptr as isize == -1 {
+            let err = std::io::Error::last_os_error();
+            unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+            return Err(err.into());
+        }
+
+        debug!("Created coverage map with shm id {}", id);
+        Ok(Self {
+            id,
+            ptr: ptr as *mut u8,
+        })
+    }
+
+    pub fn id(&self) -> i32 {
+        self.id
+    }
+
+    pub fn clear(&self) {
+        unsafe { std::ptr::write_bytes(self.ptr, 0, MAP_SIZE) };
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, MAP_SIZE) }
+    }
+}
+
+impl Drop for ShmMap {
+    fn drop(&mut self) {
+        unsafe {
+            libc::shmdt(self.ptr as *const libc::c_void);
+            libc::shmctl(self.id, libc::IPC_RMID, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Buckets hit counts the same way AFL does
+/// so that loop iterations only count as new coverage when they change in magnitude
+fn classify(count: u8) -> u8 {
+    match count {
+        0 => 0,
+        1 => 1,
+        2 => 2,
+        3 => 4,
+        4..=7 => 8,
+        8..=15 => 16,
+        16..=31 => 32,
+        32..=127 => 64,
+        _ => 128,
+    }
+}
+
+/// Coverage feedback for instrumented shell targets.
+/// Every worker owns its own map while the seen edges and the corpus
+/// of inputs that found new edges are shared by all workers
+#[derive(Clone)]
+pub struct Coverage {
+    map: Arc<ShmMap>,
+    virgin: Arc<Mutex<Vec<u8>>>,
+    corpus: Arc<Mutex<Vec<Word>>>,
+}
+
+impl Coverage {
+    pub fn new() -> FResult<Self> {
+        Ok(Self {
+            map: Arc::new(ShmMap::new()?),
+            virgin: Arc::new(Mutex::new(vec![0xFF; MAP_SIZE])),
+            corpus: Default::default(),
+        })
+    }
+
+    /// Create a copy with a new map that shares the seen edges and the corpus
+    pub fn for_worker(&self) -> FResult<Self> {
+        Ok(Self {
+            map: Arc::new(ShmMap::new()?),
+            ..self.clone()
+        })
+    }
+
+    /// The shared memory id that is handed to the child
+    pub fn shm_id(&self) -> i32 {
+        self.map.id()
+    }
+
+    /// Reset the map before a run
+    pub fn clear(&self) {
+        self.map.clear();
+    }
+
+    /// Check if the last run hit any new edges and mark them as seen
+    pub fn has_new_bits(&self) -> FResult<bool> {
+        let mut virgin = self.virgin.lock().map_err(|_| Error::Unknown)?;
+        let mut new_bits = false;
+        for (v, count) in virgin.iter_mut().zip(self.map.as_slice()) {
+            let bits = classify(*count);
+            if bits & *v != 0 {
+                *v &= !bits;
+                new_bits = true;
+            }
+        }
+        Ok(new_bits)
+    }
+
+    /// Keep an input that found new coverage
+    pub fn add(&self, input: Word) -> FResult<()> {
+        let mut corpus = self.corpus.lock().map_err(|_| Error::Unknown)?;
+        corpus.push(input);
+        info!("New coverage, corpus size is {}", corpus.len());
+        Ok(())
+    }
+
+    /// Get a corpus entry by index or None if the index is out of range
+    pub fn get(&self, index: usize) -> FResult<Option<Word>> {
+        let corpus = self.corpus.lock().map_err(|_| Error::Unknown)?;
+        Ok(corpus.get(index).cloned())
+    }
+
+    /// Lock the corpus to read all of its entries
+    pub fn corpus(&self) -> FResult<MutexGuard<'_, Vec<Word>>> {
+        self.corpus.lock().map_err(|_| Error::Unknown)
+    }
+
+    pub fn corpus_len(&self) -> FResult<usize> {
+        Ok(self.corpus.lock().map_err(|_| Error::Unknown)?.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Coverage, MAP_SIZE};
+
+    #[test]
+    fn new_bits() {
+        let cov = Coverage::new().unwrap();
+        assert!(!cov.has_new_bits().unwrap());
+
+        let map = unsafe { std::slice::from_raw_parts_mut(cov.map.ptr, MAP_SIZE) };
+        map[10] = 1;
+        assert!(cov.has_new_bits().unwrap());
+        assert!(!cov.has_new_bits().unwrap());
+
+        // a different hit count bucket is new coverage
+        map[10] = 5;
+        assert!(cov.has_new_bits().unwrap());
+
+        // the seen edges are shared between workers
+        let worker = cov.for_worker().unwrap();
+        let map = unsafe { std::slice::from_raw_parts_mut(worker.map.ptr, MAP_SIZE) };
+        map[10] = 5;
+        assert!(!worker.has_new_bits().unwrap());
+        cov.clear();
+    }
+}
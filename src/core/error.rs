@@ -17,6 +17,24 @@ pub enum Error {
     ArgError,
     #[error("Invalid regex")]
     InvalidRegex,
+    #[error("Invalid expression: {0}")]
+    InvalidExpression(String),
+    #[error("Word list of target is empty")]
+    EmptyWordList,
+    #[error("Malformed http request")]
+    InvalidHttpRequest,
+    #[error("Login request did not return a token")]
+    LoginFailed,
+    #[error("Replaying an iteration requires --seed or --replay-seed")]
+    MissingReplaySeed,
+    #[error("Replaying an iteration does not support --coverage")]
+    CoverageReplay,
+    #[error("Only findings with the words of their metadata can be replayed")]
+    UnreplayableFile,
+    #[error("Fork server handshake failed")]
+    ForkServerHandshake,
+    #[error("Fork server exited")]
+    ForkServerExited,
     #[error("JWT Signature error")]
     JwtSignatureError,
     #[error(transparent)]
@@ -0,0 +1,473 @@
+use std::{iter::Peekable, str::Chars};
+
+use super::{
+    config::Stream,
+    error::{Error, FResult},
+    json::JsonExpect,
+    reflect::Encoding,
+    runner::{socket::ConnErr, RunRes},
+    transform::{Expect, Word},
+};
+
+/// A number of a result that can be compared
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Metric {
+    ExitCode,
+    Status,
+    /// Length of the compared output stream
+    Len,
+    ContentLength,
+    /// Duration in ms
+    Time,
+}
+
+impl Metric {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exit_code" => Some(Self::ExitCode),
+            "status" => Some(Self::Status),
+            "len" => Some(Self::Len),
+            "content_length" => Some(Self::ContentLength),
+            "time" => Some(Self::Time),
+            _ => None,
+        }
+    }
+
+    /// The value of the metric, None if the result does not have it
+    pub fn value(&self, res: &RunRes, data: &Word) -> Option<i64> {
+        match self {
+            Metric::ExitCode => res.exit_code.map(|x| x as i64),
+            Metric::Status => res.http.as_ref().map(|x| x.status as i64),
+            Metric::Len => Some(data.len() as i64),
+            Metric::ContentLength => res.http.as_ref().map(|x| x.content_length() as i64),
+            Metric::Time => Some(res.duration.as_millis() as i64),
+        }
+    }
+}
+
+/// Comparison operator
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Cmp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Cmp {
+    pub fn compare<T: PartialOrd>(&self, a: T, b: T) -> bool {
+        match self {
+            Cmp::Eq => a == b,
+            Cmp::Ne => a != b,
+            Cmp::Lt => a < b,
+            Cmp::Le => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Ge => a >= b,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Ident(String),
+    Str(Word),
+    Num(i64),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Comma,
+    Cmp(Cmp),
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidExpression(msg.into())
+}
+
+fn tokenize(input: &str) -> FResult<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let token = match c {
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '"' => {
+                chars.next();
+                tokens.push(Token::Str(string(&mut chars)?));
+                continue;
+            }
+            '&' | '|' => {
+                chars.next();
+                if chars.next() != Some(c) {
+                    return Err(invalid(format!("expected {c}{c}")));
+                }
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+                continue;
+            }
+            '!' | '=' | '<' | '>' => {
+                chars.next();
+                let eq = chars.next_if_eq(&'=').is_some();
+                tokens.push(match (c, eq) {
+                    ('!', false) => Token::Not,
+                    ('!', true) => Token::Cmp(Cmp::Ne),
+                    ('=', true) => Token::Cmp(Cmp::Eq),
+                    ('<', false) => Token::Cmp(Cmp::Lt),
+                    ('<', true) => Token::Cmp(Cmp::Le),
+                    ('>', false) => Token::Cmp(Cmp::Gt),
+                    ('>', true) => Token::Cmp(Cmp::Ge),
+                    _ => return Err(invalid("expected ==")),
+                });
+                continue;
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut num = String::from(c);
+                chars.next();
+                while let Some(c) = chars.next_if(|x| x.is_ascii_digit()) {
+                    num.push(c);
+                }
+                tokens.push(Token::Num(
+                    num.parse()
+                        .map_err(|_| invalid(format!("invalid number {num}")))?,
+                ));
+                continue;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::new();
+                while let Some(c) = chars.next_if(|x| x.is_alphanumeric() || *x == '_' || *x == '.')
+                {
+                    ident.push(c);
+                }
+                tokens.push(Token::Ident(ident));
+                continue;
+            }
+            _ => return Err(invalid(format!("unexpected {c}"))),
+        };
+        chars.next();
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Read a string literal after the opening quote
+fn string(chars: &mut Peekable<Chars>) -> FResult<Word> {
+    let mut res = Vec::new();
+    loop {
+        let c = chars.next().ok_or(invalid("unterminated string"))?;
+        match c {
+            '"' => return Ok(res),
+            '\\' => match chars.next().ok_or(invalid("unterminated string"))? {
+                'n' => res.push(b'\n'),
+                'r' => res.push(b'\r'),
+                't' => res.push(b'\t'),
+                '0' => res.push(0),
+                'x' => {
+                    let hex: String = chars.by_ref().take(2).collect();
+                    res.push(
+                        u8::from_str_radix(&hex, 16)
+                            .map_err(|_| invalid(format!("invalid escape \\x{hex}")))?,
+                    );
+                }
+                c => {
+                    let mut buffer = [0; 4];
+                    res.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                }
+            },
+            c => {
+                let mut buffer = [0; 4];
+                res.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+            }
+        }
+    }
+}
+
+/// Recursive descent parser. ! binds stronger than && which binds stronger than ||
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expect(&mut self, token: Token) -> FResult<()> {
+        match self.next() {
+            Some(x) if x == token => Ok(()),
+            x => Err(invalid(format!("expected {token:?}, found {x:?}"))),
+        }
+    }
+
+    fn or(&mut self) -> FResult<Expect> {
+        let mut nodes = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            nodes.push(self.and()?);
+        }
+        Ok(if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            Expect::Any(nodes)
+        })
+    }
+
+    fn and(&mut self) -> FResult<Expect> {
+        let mut nodes = vec![self.not()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            nodes.push(self.not()?);
+        }
+        Ok(if nodes.len() == 1 {
+            nodes.remove(0)
+        } else {
+            Expect::All(nodes)
+        })
+    }
+
+    fn not(&mut self) -> FResult<Expect> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            Ok(Expect::Not(Box::new(self.not()?)))
+        } else {
+            self.atom()
+        }
+    }
+
+    fn str_arg(&mut self) -> FResult<Word> {
+        match self.next() {
+            Some(Token::Str(x)) => Ok(x),
+            x => Err(invalid(format!("expected string, found {x:?}"))),
+        }
+    }
+
+    fn utf8_arg(&mut self) -> FResult<String> {
+        String::from_utf8(self.str_arg()?).map_err(|_| invalid("expected utf-8 string"))
+    }
+
+    fn regex_arg(&mut self) -> FResult<regex::Regex> {
+        regex::Regex::new(&self.utf8_arg()?).map_err(|_| Error::InvalidRegex)
+    }
+
+    fn encoding_arg(&mut self) -> FResult<Encoding> {
+        let name = self.utf8_arg()?;
+        Encoding::ALL
+            .into_iter()
+            .find(|x| format!("{x:?}").eq_ignore_ascii_case(&name))
+            .ok_or_else(|| invalid(format!("unknown encoding {name}")))
+    }
+
+    fn atom(&mut self) -> FResult<Expect> {
+        let name = match self.next() {
+            Some(Token::Open) => {
+                let expect = self.or()?;
+                self.expect(Token::Close)?;
+                return Ok(expect);
+            }
+            Some(Token::Ident(name)) => name,
+            x => return Err(invalid(format!("expected expression, found {x:?}"))),
+        };
+
+        // comparators may be prefixed with the stream, e.g. stderr.contains("x")
+        let (stream, func) = match name.split_once('.') {
+            Some((stream, func)) => (Some(parse_stream(stream)?), func.to_owned()),
+            None => (None, name),
+        };
+
+        if let Some(metric) = Metric::from_name(&func) {
+            if stream.is_some() && metric != Metric::Len {
+                return Err(invalid(format!("{func} does not apply to a stream")));
+            }
+            let cmp = match self.next() {
+                Some(Token::Cmp(cmp)) => cmp,
+                x => return Err(invalid(format!("expected comparison, found {x:?}"))),
+            };
+            let value = match self.next() {
+                Some(Token::Num(x)) => x,
+                x => return Err(invalid(format!("expected number, found {x:?}"))),
+            };
+            return Ok(stream_scoped(stream, Expect::Number(metric, cmp, value)));
+        }
+
+        let expect = match func.as_str() {
+            "timeout" => Expect::Timeout,
+            "anomaly" => Expect::Anomaly(Default::default()),
+            "reflected" if self.peek() != Some(&Token::Open) => {
+                Expect::Reflection(Encoding::ALL.to_vec())
+            }
+            _ => {
+                self.expect(Token::Open)?;
+                let expect = match func.as_str() {
+                    "contains" => match self.str_arg()? {
+                        x if x.is_empty() => {
+                            return Err(invalid("contains needs a non-empty string"))
+                        }
+                        x => Expect::Contains(x),
+                    },
+                    "equals" => Expect::Equals(self.str_arg()?),
+                    "regex" => Expect::Regex(self.regex_arg()?),
+                    "header" => {
+                        let name = self.utf8_arg()?;
+                        let re = if self.peek() == Some(&Token::Comma) {
+                            self.next();
+                            Some(self.regex_arg()?)
+                        } else {
+                            None
+                        };
+                        Expect::Header(name, re)
+                    }
+                    "conn_err" => Expect::ConnErr(match self.utf8_arg()?.as_str() {
+                        "refused" => ConnErr::Refused,
+                        "reset" => ConnErr::Reset,
+                        x => return Err(invalid(format!("unknown connection error {x}"))),
+                    }),
+                    "reflected" => {
+                        let mut encodings = vec![self.encoding_arg()?];
+                        while self.peek() == Some(&Token::Comma) {
+                            self.next();
+                            encodings.push(self.encoding_arg()?);
+                        }
+                        Expect::Reflection(encodings)
+                    }
+                    "json" => Expect::Json(JsonExpect::parse(&self.utf8_arg()?)?),
+                    _ => return Err(invalid(format!("unknown function {func}"))),
+                };
+                self.expect(Token::Close)?;
+                expect
+            }
+        };
+        if let (None, Expect::Json(json)) = (stream, &expect) {
+            // json is checked against the body of http responses by default
+            return Ok(Expect::json(json.to_owned(), Stream::Stdout));
+        }
+        if stream.is_some() && !expect.applies_to_output() {
+            return Err(invalid(format!("{func} does not apply to a stream")));
+        }
+        Ok(stream_scoped(stream, expect))
+    }
+}
+
+fn parse_stream(name: &str) -> FResult<Stream> {
+    match name {
+        "stdout" => Ok(Stream::Stdout),
+        "stderr" => Ok(Stream::Stderr),
+        "both" => Ok(Stream::Both),
+        "body" => Ok(Stream::Body),
+        _ => Err(invalid(format!("unknown stream {name}"))),
+    }
+}
+
+fn stream_scoped(stream: Option<Stream>, expect: Expect) -> Expect {
+    match stream {
+        Some(stream) => Expect::Stream(stream, Box::new(expect)),
+        None => expect,
+    }
+}
+
+/// Parse an expression such as `status >= 500 && !contains("maintenance") || regex("stack trace")`
+pub fn parse(input: &str) -> FResult<Expect> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    let expect = parser.or()?;
+    if let Some(token) = parser.peek() {
+        return Err(invalid(format!("unexpected {token:?}")));
+    }
+    Ok(expect)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use crate::core::runner::{http::HttpRes, RunRes};
+
+    use super::parse;
+
+    #[test]
+    fn expressions() {
+        let res = RunRes {
+            out: b"500\n\n\ninternal error: stack trace".to_vec(),
+            err: b"warning".to_vec(),
+            http: Some(HttpRes {
+                status: 500,
+                headers: vec![("Server".into(), "nginx".into())],
+                body: b"internal error: stack trace".to_vec(),
+            }),
+            duration: Duration::from_millis(120),
+            ..Default::default()
+        };
+        let eval = |expr: &str| parse(expr).unwrap().expect(&res);
+
+        assert!(eval(
+            r#"status>=500 && !contains("maintenance") || regex("stack trace")"#
+        ));
+        assert!(eval(
+            r#"status >= 500 && !body.contains("stack")  || time > 100"#
+        ));
+        assert!(!eval(
+            r#"status >= 500 && !(body.contains("stack") || time > 100)"#
+        ));
+        assert!(eval(
+            r#"header("server", "^nginx$") && stderr.equals("warning")"#
+        ));
+        assert!(eval(
+            "body.len == 27 && content_length < 28 && status != 404"
+        ));
+        // ! binds stronger than && which binds stronger than ||
+        assert!(eval("!timeout && status == 404 || status == 500"));
+        assert!(!eval("!(timeout || status == 500)"));
+        assert!(eval(
+            r#"contains("\x69nternal error") && !contains("\n\n\n\n")"#
+        ));
+        let json = RunRes {
+            out: b"200\n\n\n{\"user\": {\"role\": \"admin\"}}".to_vec(),
+            err: b"[1, 2]".to_vec(),
+            http: Some(HttpRes {
+                status: 200,
+                headers: vec![],
+                body: b"{\"user\": {\"role\": \"admin\"}}".to_vec(),
+            }),
+            ..Default::default()
+        };
+        let eval_json = |expr: &str| parse(expr).unwrap().expect(&json);
+        assert!(eval_json(
+            r#"status == 200 && json("$.user.role == \"admin\"")"#
+        ));
+        assert!(eval_json(
+            r#"stderr.json("$[1] == 2") && !stdout.json("$")"#
+        ));
+
+        // a missing metric never matches
+        assert!(!eval("exit_code == 0"));
+
+        for invalid in [
+            "status >",
+            "status = 500",
+            "contains(\"x\"",
+            "status == 500 &&",
+            "unknown(\"x\")",
+            "stderr.status == 500",
+            "stderr.timeout",
+            "body.anomaly",
+            "contains(\"\")",
+            "contains(\"x\") contains(\"y\")",
+        ] {
+            assert!(parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
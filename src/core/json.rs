@@ -0,0 +1,304 @@
+use serde_json::Value;
+
+use super::{
+    error::{Error, FResult},
+    expr::Cmp,
+};
+
+/// A step into a json value
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Segment {
+    /// Object member. Json pointers also use keys to index arrays
+    Key(String),
+    Index(usize),
+}
+
+/// The type of a json value
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum JsonType {
+    Null,
+    Bool,
+    Number,
+    String,
+    Array,
+    Object,
+}
+
+impl JsonType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Null => Self::Null,
+            Value::Bool(_) => Self::Bool,
+            Value::Number(_) => Self::Number,
+            Value::String(_) => Self::String,
+            Value::Array(_) => Self::Array,
+            Value::Object(_) => Self::Object,
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "null" => Some(Self::Null),
+            "bool" | "boolean" => Some(Self::Bool),
+            "number" => Some(Self::Number),
+            "string" => Some(Self::String),
+            "array" => Some(Self::Array),
+            "object" => Some(Self::Object),
+            _ => None,
+        }
+    }
+}
+
+/// What is checked at the end of a path
+#[derive(Clone, PartialEq, Debug)]
+pub enum JsonCheck {
+    Exists,
+    Equals(Value),
+    NotEquals(Value),
+    Type(JsonType),
+    /// Numeric comparison
+    Number(Cmp, f64),
+    /// Number in an inclusive range
+    Range(f64, f64),
+}
+
+/// A path into a json document and a check of the value it points to
+#[derive(Clone, PartialEq, Debug)]
+pub struct JsonExpect {
+    pub path: Vec<Segment>,
+    pub check: JsonCheck,
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+    Error::InvalidExpression(msg.into())
+}
+
+/// Split the path from the check. The path ends at the first whitespace
+/// or comparison operator that is not inside brackets
+fn split_path(expr: &str) -> (&str, &str) {
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, c) in expr.char_indices() {
+        match c {
+            c if quote == Some(c) => quote = None,
+            _ if quote.is_some() => {}
+            '"' | '\'' if depth > 0 => quote = Some(c),
+            '[' => depth += 1,
+            ']' => depth -= 1,
+            c if depth == 0 && (c.is_whitespace() || "=!<>".contains(c)) => {
+                return (&expr[..i], &expr[i..]);
+            }
+            _ => {}
+        }
+    }
+    (expr, "")
+}
+
+/// Parse a json pointer (/a/0/b)
+fn parse_pointer(path: &str) -> Vec<Segment> {
+    path.split('/')
+        .skip(1)
+        .map(|x| Segment::Key(x.replace("~1", "/").replace("~0", "~")))
+        .collect()
+}
+
+/// Parse a json path ($.a[0].b or $["a b"])
+fn parse_json_path(path: &str) -> FResult<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = path
+        .strip_prefix('$')
+        .ok_or_else(|| invalid(format!("json path {path} does not start with $")))?;
+    while !rest.is_empty() {
+        if let Some(tail) = rest.strip_prefix('.') {
+            let end = tail.find(['.', '[']).unwrap_or(tail.len());
+            if end == 0 {
+                return Err(invalid(format!("empty key in {path}")));
+            }
+            segments.push(Segment::Key(tail[..end].to_owned()));
+            rest = &tail[end..];
+        } else if let Some(tail) = rest.strip_prefix('[') {
+            let tail = tail.trim_start();
+            let (segment, tail) = match tail.chars().next() {
+                Some(quote @ ('"' | '\'')) => {
+                    // keys may contain ] so the closing quote is searched first
+                    let key = &tail[1..];
+                    let end = key
+                        .find(quote)
+                        .ok_or_else(|| invalid(format!("unterminated {quote} in {path}")))?;
+                    (Segment::Key(key[..end].to_owned()), &key[end + 1..])
+                }
+                _ => {
+                    let end = tail
+                        .find(']')
+                        .ok_or_else(|| invalid(format!("unterminated [ in {path}")))?;
+                    let inner = tail[..end].trim();
+                    let index = inner
+                        .parse()
+                        .map_err(|_| invalid(format!("invalid index {inner} in {path}")))?;
+                    (Segment::Index(index), &tail[end..])
+                }
+            };
+            segments.push(segment);
+            rest = tail
+                .trim_start()
+                .strip_prefix(']')
+                .ok_or_else(|| invalid(format!("unterminated [ in {path}")))?;
+        } else {
+            return Err(invalid(format!("unexpected {rest} in {path}")));
+        }
+    }
+    Ok(segments)
+}
+
+fn parse_number(value: &str) -> FResult<f64> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| invalid(format!("invalid number {value}")))
+}
+
+fn parse_value(value: &str) -> FResult<Value> {
+    serde_json::from_str(value.trim()).map_err(|_| invalid(format!("invalid json value {value}")))
+}
+
+impl JsonExpect {
+    /// Parse a path followed by an optional check, e.g.
+    /// `$.error.code == 500`, `/user/role != "admin"`, `$.items is array`,
+    /// `$.count >= 10` or `$.count in 1..10`.
+    /// A path without a check only has to exist
+    pub fn parse(expr: &str) -> FResult<Self> {
+        let (path, check) = split_path(expr.trim());
+        let path = if path.starts_with('/') || path.is_empty() {
+            parse_pointer(path)
+        } else {
+            parse_json_path(path)?
+        };
+
+        let check = check.trim();
+        let check = if check.is_empty() {
+            JsonCheck::Exists
+        } else if let Some(value) = check.strip_prefix("==") {
+            JsonCheck::Equals(parse_value(value)?)
+        } else if let Some(value) = check.strip_prefix("!=") {
+            JsonCheck::NotEquals(parse_value(value)?)
+        } else if let Some(value) = check.strip_prefix("<=") {
+            JsonCheck::Number(Cmp::Le, parse_number(value)?)
+        } else if let Some(value) = check.strip_prefix(">=") {
+            JsonCheck::Number(Cmp::Ge, parse_number(value)?)
+        } else if let Some(value) = check.strip_prefix('<') {
+            JsonCheck::Number(Cmp::Lt, parse_number(value)?)
+        } else if let Some(value) = check.strip_prefix('>') {
+            JsonCheck::Number(Cmp::Gt, parse_number(value)?)
+        } else if let Some(name) = check.strip_prefix("is ") {
+            JsonCheck::Type(
+                JsonType::from_name(name.trim())
+                    .ok_or_else(|| invalid(format!("unknown json type {name}")))?,
+            )
+        } else if let Some((from, to)) = check.strip_prefix("in ").and_then(|x| x.split_once(".."))
+        {
+            JsonCheck::Range(parse_number(from)?, parse_number(to)?)
+        } else {
+            return Err(invalid(format!("unknown json check {check}")));
+        };
+
+        Ok(Self { path, check })
+    }
+
+    /// Find the value the path points to
+    pub fn lookup<'a>(&self, value: &'a Value) -> Option<&'a Value> {
+        self.path
+            .iter()
+            .try_fold(value, |value, segment| match (segment, value) {
+                (Segment::Key(key), Value::Object(map)) => map.get(key),
+                (Segment::Key(key), Value::Array(array)) => array.get(key.parse::<usize>().ok()?),
+                (Segment::Index(i), Value::Array(array)) => array.get(*i),
+                _ => None,
+            })
+    }
+
+    /// true if data is json and the value at the path passes the check
+    pub fn matches(&self, data: &[u8]) -> bool {
+        let Ok(json) = serde_json::from_slice::<Value>(data) else {
+            return false;
+        };
+        let Some(value) = self.lookup(&json) else {
+            return false;
+        };
+
+        match &self.check {
+            JsonCheck::Exists => true,
+            JsonCheck::Equals(expected) => json_eq(value, expected),
+            JsonCheck::NotEquals(expected) => !json_eq(value, expected),
+            JsonCheck::Type(expected) => JsonType::of(value) == *expected,
+            JsonCheck::Number(cmp, expected) => value
+                .as_f64()
+                .map(|x| cmp.compare(x, *expected))
+                .unwrap_or(false),
+            JsonCheck::Range(from, to) => value
+                .as_f64()
+                .map(|x| (*from..=*to).contains(&x))
+                .unwrap_or(false),
+        }
+    }
+}
+
+/// Equality that treats 500 and 500.0 as the same number
+fn json_eq(a: &Value, b: &Value) -> bool {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::JsonExpect;
+
+    #[test]
+    fn json_paths() {
+        let body = br#"{"error": {"code": 500, "message": "db down"},
+            "user": {"role": "admin", "id": 7},
+            "items": [{"id": 1}, {"id": 2.5}],
+            "a/b": null}"#;
+        let matches = |expr: &str| JsonExpect::parse(expr).unwrap().matches(body);
+
+        assert!(matches("$.error.code == 500"));
+        assert!(matches("$.error.code==500.0"));
+        assert!(matches(r#"$.user.role == "admin""#));
+        assert!(!matches(r#"$.user.role != "admin""#));
+        assert!(matches("$.error"));
+        assert!(!matches("$.error.trace"));
+        assert!(matches("$.items[1].id > 2"));
+        assert!(matches(r#"$["user"]['id'] in 1..10"#));
+        assert!(!matches("$.items[2]"));
+        assert!(matches("$.items is array"));
+        assert!(matches("$.error.message is string"));
+        assert!(!matches("$.user.role >= 1"));
+
+        // json pointers
+        assert!(matches("/items/0/id == 1"));
+        assert!(matches("/a~1b is null"));
+        assert!(matches("$ is object"));
+
+        assert!(!JsonExpect::parse("$.user").unwrap().matches(b"not json"));
+        // quoted keys may contain brackets, operators and multi-byte characters
+        let body = r#"{"a]b": 1, "é": {"x=y": 2}}"#.as_bytes();
+        let matches = |expr: &str| JsonExpect::parse(expr).unwrap().matches(body);
+        assert!(matches(r#"$["a]b"] == 1"#));
+        assert!(matches(r#"$[ "é" ]['x=y'] == 2"#));
+
+        for invalid in [
+            "$.a ==",
+            "$.a is text",
+            "$.a[x]",
+            "a.b",
+            "$.a ~ 1",
+            r#"$["ab]"#,
+            r#"$["aé]"#,
+            r#"$["a"b]"#,
+            r#"$['a"]"#,
+        ] {
+            assert!(JsonExpect::parse(invalid).is_err(), "{invalid}");
+        }
+    }
+}
@@ -1,5 +1,12 @@
+pub mod baseline;
 pub mod config;
+pub mod corpus;
+pub mod coverage;
 pub mod error;
+pub mod expr;
+pub mod json;
+pub mod mutate;
 pub mod rand;
+pub mod reflect;
 pub mod runner;
 pub mod transform;
@@ -0,0 +1,289 @@
+use std::io::Read;
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+
+use super::{config::Config, error::FResult, rand::Rand, transform::Word};
+
+/// The largest block that is inserted, deleted or duplicated at once
+const MAX_BLOCK_LEN: usize = 64;
+
+const INTERESTING_8: &[i8] = &[-128, -1, 0, 1, 16, 32, 64, 100, 127];
+const INTERESTING_16: &[i16] = &[-32768, -129, 128, 255, 256, 512, 1000, 1024, 4096, 32767];
+const INTERESTING_32: &[i32] = &[
+    -2147483648,
+    -100663046,
+    -32769,
+    32768,
+    65535,
+    65536,
+    100663045,
+    2147483647,
+];
+
+/// A single mutation that can be applied to the input
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Mutation {
+    /// Flip a single bit
+    BitFlip,
+    /// Invert a single byte
+    ByteFlip,
+    /// Overwrite 1, 2 or 4 bytes with an interesting integer
+    InterestingInt,
+    /// Insert a block of random bytes
+    BlockInsert,
+    /// Delete a block of bytes
+    BlockDelete,
+    /// Copy a block of bytes to a different position
+    BlockDuplicate,
+    /// Combine the input with one of the splice files or corpus entries
+    Splice,
+}
+
+/// Applies randomly selected mutations to the input before targets are replaced
+#[derive(Clone, Default)]
+pub struct Mutator {
+    mutations: Vec<Mutation>,
+    n_mutation: u32,
+    splice: Vec<Word>,
+}
+
+impl Mutator {
+    pub fn new(mutations: Vec<Mutation>, n_mutation: u32, splice: Vec<Word>) -> Self {
+        Self {
+            mutations,
+            n_mutation,
+            splice,
+        }
+    }
+
+    /// Create from config
+    pub fn from_cfg(cfg: &Config) -> FResult<Self> {
+        let mut splice = Vec::new();
+        for path in &cfg.splice_file {
+            let mut f = std::fs::File::open(path)?;
+            let mut buffer = Vec::new();
+            f.read_to_end(&mut buffer)?;
+            splice.push(buffer);
+        }
+
+        Ok(Self::new(cfg.mutate.to_owned(), cfg.n_mutation, splice))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.mutations.is_empty() && self.n_mutation > 0
+    }
+
+    /// Apply between 1 and n_mutation stacked mutations to the input.
+    /// corpus holds groups of inputs that are spliced like the splice files
+    pub fn mutate(&self, input: &[u8], corpus: &[&[Word]], rand: &mut Rand) -> FResult<Word> {
+        let mut data = input.to_vec();
+        if !self.is_enabled() {
+            return Ok(data);
+        }
+
+        let n = below(rand, self.n_mutation as usize)? + 1;
+        for _ in 0..n {
+            let mutation = self.mutations[below(rand, self.mutations.len())?];
+            self.apply(mutation, &mut data, corpus, rand)?;
+        }
+        Ok(data)
+    }
+
+    /// Apply a single mutation in place.
+    /// Mutations that require more data than is available do nothing
+    pub fn apply(
+        &self,
+        mutation: Mutation,
+        data: &mut Word,
+        corpus: &[&[Word]],
+        rand: &mut Rand,
+    ) -> FResult<()> {
+        match mutation {
+            Mutation::BitFlip => {
+                if !data.is_empty() {
+                    let bit = below(rand, data.len() * 8)?;
+                    data[bit / 8] ^= 0x80 >> (bit % 8);
+                }
+            }
+            Mutation::ByteFlip => {
+                if !data.is_empty() {
+                    let at = below(rand, data.len())?;
+                    data[at] ^= 0xFF;
+                }
+            }
+            Mutation::InterestingInt => {
+                let value: Vec<u8> = match below(rand, 3)? {
+                    0 => vec![INTERESTING_8[below(rand, INTERESTING_8.len())?] as u8],
+                    1 => endian(
+                        INTERESTING_16[below(rand, INTERESTING_16.len())?].to_le_bytes(),
+                        rand,
+                    )?,
+                    _ => endian(
+                        INTERESTING_32[below(rand, INTERESTING_32.len())?].to_le_bytes(),
+                        rand,
+                    )?,
+                };
+                if data.len() >= value.len() {
+                    let at = below(rand, data.len() - value.len() + 1)?;
+                    data[at..at + value.len()].copy_from_slice(&value);
+                }
+            }
+            Mutation::BlockInsert => {
+                let len = below(rand, MAX_BLOCK_LEN)? + 1;
+                let at = below(rand, data.len() + 1)?;
+                let mut block = Vec::with_capacity(len);
+                for _ in 0..len {
+                    block.push(rand.next_gen()? as u8);
+                }
+                data.splice(at..at, block);
+            }
+            Mutation::BlockDelete => {
+                if data.len() > 1 {
+                    let len = block_len(rand, data.len() - 1)?;
+                    let at = below(rand, data.len() - len + 1)?;
+                    data.drain(at..at + len);
+                }
+            }
+            Mutation::BlockDuplicate => {
+                if !data.is_empty() {
+                    let len = block_len(rand, data.len())?;
+                    let from = below(rand, data.len() - len + 1)?;
+                    let to = below(rand, data.len() + 1)?;
+                    let block = data[from..from + len].to_vec();
+                    data.splice(to..to, block);
+                }
+            }
+            Mutation::Splice => {
+                let len = self.splice.len() + corpus.iter().map(|x| x.len()).sum::<usize>();
+                if len > 0 {
+                    let index = below(rand, len)?;
+                    let mut others = self.splice.iter().chain(corpus.iter().copied().flatten());
+                    let Some(other) = others.nth(index) else {
+                        return Ok(());
+                    };
+                    let head = below(rand, data.len() + 1)?;
+                    let tail = below(rand, other.len() + 1)?;
+                    data.truncate(head);
+                    data.extend_from_slice(&other[tail..]);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// random number in 0..n, n == 0 always results in 0
+fn below(rand: &mut Rand, n: usize) -> FResult<usize> {
+    if n == 0 {
+        Ok(0)
+    } else {
+        Ok((rand.next_range(0, n as u64)? as usize).min(n - 1))
+    }
+}
+
+/// random block length in 1..=min(max, MAX_BLOCK_LEN)
+fn block_len(rand: &mut Rand, max: usize) -> FResult<usize> {
+    Ok(below(rand, max.min(MAX_BLOCK_LEN))? + 1)
+}
+
+/// randomly swaps a little endian value to big endian
+fn endian<const N: usize>(mut value: [u8; N], rand: &mut Rand) -> FResult<Vec<u8>> {
+    if below(rand, 2)? == 1 {
+        value.reverse();
+    }
+    Ok(value.to_vec())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::core::rand::Rand;
+
+    use super::{Mutation, Mutator};
+
+    fn mutate_with(mutation: Mutation, input: &[u8]) -> Vec<u8> {
+        let mutator = Mutator::new(vec![mutation], 1, vec![b"spliced".to_vec()]);
+        mutator.mutate(input, &[], &mut Rand::from_seed(1)).unwrap()
+    }
+
+    #[test]
+    fn mutations() {
+        let input = b"hello world";
+
+        let flipped = mutate_with(Mutation::BitFlip, input);
+        assert_eq!(input.len(), flipped.len());
+        let diff: u32 = input
+            .iter()
+            .zip(flipped.iter())
+            .map(|(a, b)| (a ^ b).count_ones())
+            .sum();
+        assert_eq!(1, diff);
+
+        let flipped = mutate_with(Mutation::ByteFlip, input);
+        assert_eq!(
+            1,
+            input
+                .iter()
+                .zip(flipped.iter())
+                .filter(|(a, b)| a != b)
+                .count()
+        );
+
+        assert!(mutate_with(Mutation::BlockInsert, input).len() > input.len());
+        assert!(mutate_with(Mutation::BlockDelete, input).len() < input.len());
+        assert!(mutate_with(Mutation::BlockDuplicate, input).len() > input.len());
+        assert_eq!(
+            input.len(),
+            mutate_with(Mutation::InterestingInt, input).len()
+        );
+
+        assert!(mutate_with(Mutation::BitFlip, b"").is_empty());
+    }
+
+    #[test]
+    fn splice_corpus() {
+        let mutator = Mutator::new(vec![Mutation::Splice], 1, vec![]);
+        let mut rand = Rand::from_seed(3);
+        assert_eq!(
+            b"input".to_vec(),
+            mutator.mutate(b"input", &[&[]], &mut rand).unwrap()
+        );
+
+        let seeds = vec![b"seed".to_vec()];
+        let coverage = vec![b"coverage".to_vec()];
+        for _ in 0..16 {
+            let spliced = mutator
+                .mutate(b"input", &[&seeds, &coverage], &mut rand)
+                .unwrap();
+            // a head of the input followed by a tail of a corpus entry
+            assert!((0..=5).any(|head| {
+                spliced.starts_with(&b"input"[..head])
+                    && [b"seed".as_slice(), b"coverage"]
+                        .iter()
+                        .any(|x| x.ends_with(&spliced[head..]))
+            }));
+        }
+    }
+
+    #[test]
+    fn reproducible() {
+        let mutator = Mutator::new(
+            vec![
+                Mutation::BitFlip,
+                Mutation::BlockInsert,
+                Mutation::BlockDelete,
+                Mutation::Splice,
+            ],
+            8,
+            vec![b"spliced".to_vec()],
+        );
+        let a = mutator
+            .mutate(b"input data", &[], &mut Rand::from_seed(42))
+            .unwrap();
+        let b = mutator
+            .mutate(b"input data", &[], &mut Rand::from_seed(42))
+            .unwrap();
+        assert_eq!(a, b);
+    }
+}
@@ -1,6 +1,6 @@
 use std::{
     fs::File,
-    io::BufReader,
+    io::{BufReader, Read},
     path::{Path, PathBuf},
 };
 
@@ -12,8 +12,12 @@ use super::error::FResult;
 /// Generic file reader with a buffer
 pub struct FileReader {
     path: PathBuf,
-    reader: Option<Box<dyn std::io::Read>>,
+    reader: Option<Box<dyn std::io::Read + Send>>,
     buffer: [u8; std::mem::size_of::<u64>()],
+    /// Index of the first value that is read
+    offset: u64,
+    /// Only every n-th value is read
+    stride: u64,
 }
 
 /// Rng wrapper that can either use the rand crate
@@ -28,7 +32,7 @@ impl Clone for Rand {
     fn clone(&self) -> Self {
         match self {
             Self::Random(val) => Self::Random(val.clone()),
-            Self::File(r) => Self::from_path(&r.path),
+            Self::File(r) => Self::from_path_strided(&r.path, r.offset, r.stride),
         }
     }
 }
@@ -39,6 +43,15 @@ impl Default for Rand {
     }
 }
 
+/// Derive the seed of a single iteration from the seed of a run (splitmix64).
+/// Every iteration can be reproduced without running the ones before it
+pub fn iteration_seed(seed: u64, iteration: u32) -> u64 {
+    let mut z = seed.wrapping_add((iteration as u64 + 1).wrapping_mul(0x9E3779B97F4A7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
 impl Rand {
     /// Create a new random file from seed using the rand crate
     pub fn from_seed(seed: u64) -> Self {
@@ -47,23 +60,43 @@ impl Rand {
 
     /// Create a new file-based rand
     pub fn from_path(path: &Path) -> Self {
+        Self::from_path_strided(path, 0, 1)
+    }
+
+    /// Create a file-based rand that reads every stride-th value starting at offset.
+    /// Workers that share a file use distinct offsets so that they read distinct values
+    pub fn from_path_strided(path: &Path, offset: u64, stride: u64) -> Self {
         Self::File(FileReader {
             path: path.into(),
             reader: None,
             buffer: [0; std::mem::size_of::<u64>()],
+            offset,
+            stride: stride.max(1),
         })
     }
 
+    /// Skip n values of the file. Reaching the end is reported by the next read
+    fn skip(r: &mut FileReader, n: u64) -> FResult<()> {
+        if let Some(reader) = r.reader.as_mut() {
+            let len = n * std::mem::size_of::<u64>() as u64;
+            std::io::copy(&mut reader.by_ref().take(len), &mut std::io::sink())?;
+        }
+        Ok(())
+    }
+
     /// Read u64 form the file-based rand
     fn read_u64_from(r: &mut FileReader) -> FResult<u64> {
         if r.reader.is_none() {
             debug!("Opening file {:?}", r.path);
             r.reader = Some(Box::new(BufReader::new(File::open(&r.path)?)));
+            Self::skip(r, r.offset)?;
         }
         // this should always be ok
         let reader = r.reader.as_mut().unwrap();
         reader.read_exact(&mut r.buffer)?;
-        Ok(u64::from_ne_bytes(r.buffer))
+        let value = u64::from_ne_bytes(r.buffer);
+        Self::skip(r, r.stride - 1)?;
+        Ok(value)
     }
 
     /// generate a random u64
@@ -82,3 +115,28 @@ impl Rand {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::Rand;
+
+    #[test]
+    fn strided_files() {
+        let path = std::env::temp_dir().join(format!("oxifuzz-rand-{}", std::process::id()));
+        let values: Vec<u8> = (0..8u64).flat_map(|x| x.to_ne_bytes()).collect();
+        std::fs::write(&path, values).unwrap();
+
+        let read =
+            |rand: &mut Rand| -> Vec<u64> { (0..4).map(|_| rand.next_gen().unwrap()).collect() };
+        assert_eq!(vec![0, 1, 2, 3], read(&mut Rand::from_path(&path)));
+        assert_eq!(
+            vec![0, 2, 4, 6],
+            read(&mut Rand::from_path_strided(&path, 0, 2))
+        );
+        let mut second = Rand::from_path_strided(&path, 1, 2);
+        assert_eq!(vec![1, 3, 5, 7], read(&mut second));
+        assert!(second.next_gen().is_err());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+
+use super::transform::Word;
+
+/// Characters that are not percent-encoded
+const URL_UNRESERVED: &[u8] = b"-._~";
+
+/// An encoding a reflected word may appear in
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Encoding {
+    /// The word as it was sent
+    Raw,
+    /// HTML entities for &, <, >, " and '
+    Html,
+    /// Percent-encoding as used in urls and forms
+    Url,
+    /// Escaped as a json string
+    Json,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 4] = [Encoding::Raw, Encoding::Html, Encoding::Url, Encoding::Json];
+
+    /// Every common form of the word in this encoding
+    pub fn encode(&self, word: &[u8]) -> Vec<Word> {
+        match self {
+            Encoding::Raw => vec![word.to_vec()],
+            Encoding::Html => vec![html_encode(word, "&#39;"), html_encode(word, "&#x27;")],
+            Encoding::Url => vec![url_encode(word, false), url_encode(word, true)],
+            Encoding::Json => vec![json_encode(word)],
+        }
+    }
+}
+
+fn html_encode(word: &[u8], quote: &str) -> Word {
+    let mut res = Vec::with_capacity(word.len());
+    for c in word {
+        match c {
+            b'&' => res.extend_from_slice(b"&amp;"),
+            b'<' => res.extend_from_slice(b"&lt;"),
+            b'>' => res.extend_from_slice(b"&gt;"),
+            b'"' => res.extend_from_slice(b"&quot;"),
+            b'\'' => res.extend_from_slice(quote.as_bytes()),
+            c => res.push(*c),
+        }
+    }
+    res
+}
+
+/// Percent-encode everything but unreserved characters.
+/// Forms encode spaces as +
+fn url_encode(word: &[u8], form: bool) -> Word {
+    let mut res = Vec::with_capacity(word.len());
+    for c in word {
+        if c.is_ascii_alphanumeric() || URL_UNRESERVED.contains(c) {
+            res.push(*c);
+        } else if form && *c == b' ' {
+            res.push(b'+');
+        } else {
+            res.extend_from_slice(format!("%{c:02X}").as_bytes());
+        }
+    }
+    res
+}
+
+fn json_encode(word: &[u8]) -> Word {
+    let encoded = serde_json::to_string(&String::from_utf8_lossy(word)).unwrap_or_default();
+    // strip the quotes of the json string
+    encoded.as_bytes()[1..encoded.len().max(2) - 1].to_vec()
+}
+
+fn contains(data: &[u8], word: &[u8]) -> bool {
+    data.windows(word.len()).any(|x| x == word)
+}
+
+/// true if any of the words appears in the data in any of the encodings.
+/// Empty words are ignored
+pub fn is_reflected<'a>(
+    words: impl IntoIterator<Item = &'a Word>,
+    data: &[u8],
+    encodings: &[Encoding],
+) -> bool {
+    words.into_iter().filter(|x| !x.is_empty()).any(|word| {
+        encodings
+            .iter()
+            .flat_map(|x| x.encode(word))
+            .any(|x| contains(data, &x))
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_reflected, Encoding};
+
+    #[test]
+    fn reflections() {
+        let words = vec![b"<script>alert('x y')</script>".to_vec(), b"".to_vec()];
+        let reflected = |data: &[u8], encoding: Encoding| is_reflected(&words, data, &[encoding]);
+
+        assert!(reflected(
+            b"<p>Search: <script>alert('x y')</script></p>",
+            Encoding::Raw
+        ));
+        assert!(reflected(
+            b"<p>&lt;script&gt;alert(&#39;x y&#39;)&lt;/script&gt;</p>",
+            Encoding::Html
+        ));
+        assert!(reflected(
+            b"<p>&lt;script&gt;alert(&#x27;x y&#x27;)&lt;/script&gt;</p>",
+            Encoding::Html
+        ));
+        assert!(reflected(
+            b"Location: /?q=%3Cscript%3Ealert%28%27x%20y%27%29%3C%2Fscript%3E",
+            Encoding::Url
+        ));
+        assert!(reflected(
+            b"Location: /?q=%3Cscript%3Ealert%28%27x+y%27%29%3C%2Fscript%3E",
+            Encoding::Url
+        ));
+        assert!(!reflected(
+            b"<p>&lt;script&gt;alert(&#39;x y&#39;)&lt;/script&gt;</p>",
+            Encoding::Raw
+        ));
+
+        let words = vec![b"a\"b\\c\n".to_vec()];
+        assert!(is_reflected(
+            &words,
+            br#"{"q": "a\"b\\c\n"}"#,
+            &Encoding::ALL
+        ));
+        assert!(!is_reflected(&words, b"no reflection", &Encoding::ALL));
+        assert!(!is_reflected(&[vec![]], b"anything", &Encoding::ALL));
+    }
+}
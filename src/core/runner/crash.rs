@@ -0,0 +1,149 @@
+use std::{os::unix::process::ExitStatusExt, process::ExitStatus};
+
+/// Markers of sanitizer reports in order of preference
+const SANITIZER_MARKERS: &[&str] = &[
+    "SUMMARY: AddressSanitizer:",
+    "SUMMARY: UndefinedBehaviorSanitizer:",
+    "SUMMARY: MemorySanitizer:",
+    "SUMMARY: ThreadSanitizer:",
+    "SUMMARY: LeakSanitizer:",
+    "ERROR: AddressSanitizer:",
+    "ERROR: LeakSanitizer:",
+    "WARNING: MemorySanitizer:",
+    "WARNING: ThreadSanitizer:",
+    "runtime error:",
+];
+
+/// Signals that are sent by a faulting target. Signals like SIGTERM, SIGKILL
+/// or SIGPIPE are usually sent from outside and are not crashes
+const CRASH_SIGNALS: &[i32] = &[
+    libc::SIGSEGV,
+    libc::SIGABRT,
+    libc::SIGILL,
+    libc::SIGFPE,
+    libc::SIGBUS,
+    libc::SIGTRAP,
+    libc::SIGSYS,
+];
+
+/// A crash of a shell target
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Crash {
+    /// The signal that terminated the target
+    pub signal: Option<i32>,
+    /// The first line of a sanitizer report on stderr
+    pub sanitizer: Option<String>,
+}
+
+impl Crash {
+    /// Detect a crash from the exit status and stderr of a target.
+    /// A crash is either a termination by a crash signal or a sanitizer report
+    pub fn detect(status: Option<&ExitStatus>, stderr: &[u8]) -> Option<Self> {
+        let crash = Self {
+            signal: status
+                .and_then(|x| x.signal())
+                .filter(|x| CRASH_SIGNALS.contains(x)),
+            sanitizer: parse_sanitizer(stderr),
+        };
+        if crash.signal.is_some() || crash.sanitizer.is_some() {
+            Some(crash)
+        } else {
+            None
+        }
+    }
+
+    /// Short human readable description of the crash
+    pub fn summary(&self) -> String {
+        match (&self.sanitizer, self.signal) {
+            (Some(sanitizer), _) => sanitizer.to_owned(),
+            (None, Some(signal)) => signal_name(signal),
+            (None, None) => "unknown crash".into(),
+        }
+    }
+}
+
+/// Name of a signal, unknown signals are returned as their number
+pub fn signal_name(signal: i32) -> String {
+    match signal {
+        libc::SIGSEGV => "SIGSEGV".into(),
+        libc::SIGABRT => "SIGABRT".into(),
+        libc::SIGILL => "SIGILL".into(),
+        libc::SIGFPE => "SIGFPE".into(),
+        libc::SIGBUS => "SIGBUS".into(),
+        libc::SIGTRAP => "SIGTRAP".into(),
+        libc::SIGSYS => "SIGSYS".into(),
+        libc::SIGKILL => "SIGKILL".into(),
+        libc::SIGTERM => "SIGTERM".into(),
+        libc::SIGINT => "SIGINT".into(),
+        libc::SIGPIPE => "SIGPIPE".into(),
+        _ => format!("signal {signal}"),
+    }
+}
+
+/// Find the most descriptive line of a sanitizer report
+pub fn parse_sanitizer(stderr: &[u8]) -> Option<String> {
+    let stderr = String::from_utf8_lossy(stderr);
+    SANITIZER_MARKERS.iter().find_map(|marker| {
+        stderr.lines().find_map(|line| {
+            line.find(marker).map(|i| {
+                let report = &line[i..];
+                let report = ["SUMMARY: ", "ERROR: ", "WARNING: "]
+                    .iter()
+                    .fold(report, |acc, x| acc.trim_start_matches(x));
+                report.trim().to_owned()
+            })
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::{parse_sanitizer, Crash};
+
+    #[test]
+    fn sanitizer_reports() {
+        let asan = b"=================================================================
+==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address 0x602000000011
+READ of size 1 at 0x602000000011 thread T0
+SUMMARY: AddressSanitizer: heap-buffer-overflow parser.c:12 in parse
+";
+        assert_eq!(
+            Some("AddressSanitizer: heap-buffer-overflow parser.c:12 in parse".into()),
+            parse_sanitizer(asan)
+        );
+
+        let ubsan = b"parser.c:20:5: runtime error: signed integer overflow\n";
+        assert_eq!(
+            Some("runtime error: signed integer overflow".into()),
+            parse_sanitizer(ubsan)
+        );
+
+        assert_eq!(None, parse_sanitizer(b"parse error at line 1\n"));
+    }
+
+    #[test]
+    fn signals() {
+        let exit = |signal: &str| {
+            Command::new("sh")
+                .arg("-c")
+                .arg(format!("kill -{signal} $$"))
+                .status()
+                .unwrap()
+        };
+
+        let crash = Crash::detect(Some(&exit("SEGV")), b"").unwrap();
+        assert_eq!(Some(libc::SIGSEGV), crash.signal);
+        assert_eq!("SIGSEGV", crash.summary());
+        assert!(Crash::detect(Some(&exit("ABRT")), b"").is_some());
+
+        for signal in ["TERM", "KILL", "INT", "PIPE"] {
+            assert_eq!(None, Crash::detect(Some(&exit(signal)), b""), "{signal}");
+        }
+        // a sanitizer report is a crash whatever the exit status is
+        let crash =
+            Crash::detect(Some(&exit("TERM")), b"x.c:1:1: runtime error: overflow").unwrap();
+        assert_eq!(None, crash.signal);
+    }
+}
@@ -0,0 +1,316 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use log::{debug, info};
+
+use crate::core::{
+    config::Config,
+    corpus::CorpusDir,
+    error::{Error, FResult},
+    transform::{Context, ExecRes, IterState, OutputFmt, Word},
+};
+
+use super::{
+    default_command_expect, replace_fuzz, target_command, wait_child, CommandRunnerKind, RunRes,
+};
+
+/// Placeholder in command args that is replaced with the path of the input file
+pub const DEFAULT_FILE_TARGET: &str = "@@";
+
+/// Name of the input file if no template is given
+pub const DEFAULT_FILE_NAME: &str = "oxifuzz-{pid}-{iteration}";
+
+/// Input files of all workers.
+/// Equal inputs share a file if its name only depends on the hash
+#[derive(Default, Debug)]
+pub struct InputFiles {
+    /// Number of runs that currently use a file
+    in_use: HashMap<PathBuf, usize>,
+    /// Files of interesting results that are never removed
+    kept: HashSet<PathBuf>,
+}
+
+/// A shell target that reads its input from a file
+#[derive(Clone)]
+pub struct FileDrop {
+    pub cmd: String,
+    pub cmd_args: Vec<String>,
+    pub cmd_arg_target: String,
+    /// Replaced with the path of the input file in the command args
+    pub file_target: String,
+    pub timeout: Option<u32>,
+    pub dir: PathBuf,
+    pub extension: Option<String>,
+    /// The file name. {iteration}, {pid} and {hash} are replaced
+    pub name: String,
+    pub files: Arc<Mutex<InputFiles>>,
+}
+
+impl FileDrop {
+    pub fn from_cfg(cfg: &Config) -> FResult<Option<Self>> {
+        let Some(cmd) = cfg.cmd()? else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            cmd,
+            cmd_args: cfg.cmd_args()?.unwrap_or(vec![]),
+            cmd_arg_target: cfg.exec_target.to_owned(),
+            file_target: cfg.file_target.to_owned(),
+            timeout: cfg.exec_timeout,
+            dir: cfg.file_dir.to_owned().unwrap_or(std::env::temp_dir()),
+            extension: cfg.file_ext.to_owned(),
+            name: cfg.file_name.to_owned().unwrap_or(DEFAULT_FILE_NAME.into()),
+            files: Default::default(),
+        }))
+    }
+
+    /// The path of the input file for an iteration.
+    /// Names that only depend on the hash are shared by equal inputs
+    pub fn path(&self, data: &[u8], iteration: u32) -> PathBuf {
+        let mut name = self
+            .name
+            .replace("{iteration}", &iteration.to_string())
+            .replace("{pid}", &std::process::id().to_string());
+        if name.contains("{hash}") {
+            name = name.replace("{hash}", &CorpusDir::name(data));
+        }
+        if let Some(extension) = &self.extension {
+            name.push('.');
+            name.push_str(extension.trim_start_matches('.'));
+        }
+        self.dir.join(name)
+    }
+
+    /// Write the input file of a run. A shared file that is in use or was kept
+    /// already has the same content and is left alone
+    fn write(&self, path: &Path, data: &[u8]) -> FResult<()> {
+        let shared = self.name.contains("{hash}");
+        let mut files = self.files.lock().map_err(|_| Error::Unknown)?;
+        let users = files.in_use.entry(path.to_owned()).or_default();
+        *users += 1;
+        if shared && (*users > 1 || files.kept.contains(path)) {
+            return Ok(());
+        }
+        debug!("Writing input to {:?}", path);
+        files.kept.remove(path);
+        if let Err(err) = std::fs::write(path, data) {
+            Self::release(&mut files, path);
+            return Err(err.into());
+        }
+        Ok(())
+    }
+
+    fn release(files: &mut InputFiles, path: &Path) -> bool {
+        match files.in_use.get_mut(path) {
+            Some(users) if *users > 1 => {
+                *users -= 1;
+                false
+            }
+            _ => {
+                files.in_use.remove(path);
+                true
+            }
+        }
+    }
+
+    /// Release the input file after a run. It is kept if the result is interesting,
+    /// otherwise it is removed once no other run uses it and it was never kept
+    pub(crate) fn done(&self, path: &Path, keep: bool) -> FResult<()> {
+        let mut files = self.files.lock().map_err(|_| Error::Unknown)?;
+        let unused = Self::release(&mut files, path);
+        if keep {
+            files.kept.insert(path.to_owned());
+        } else if unused && !files.kept.contains(path) {
+            remove(path);
+        }
+        Ok(())
+    }
+}
+
+/// The command runner for file targets.
+/// It writes the input to a file and passes its path to the command
+pub fn file_command_runner(
+    ctx: &Context,
+    runner: &CommandRunnerKind,
+    data: &Word,
+    state: &mut IterState,
+) -> FResult<RunRes> {
+    if let CommandRunnerKind::File(file) = runner {
+        let path = file.path(data, state.iteration);
+        let path_str = path.to_string_lossy();
+        let args: Vec<String> = file
+            .cmd_args
+            .iter()
+            .map(|x| {
+                replace_fuzz(
+                    &x.replace(&file.file_target, &path_str),
+                    &file.cmd_arg_target,
+                    ctx,
+                    state,
+                )
+            })
+            .try_collect()?;
+
+        if ctx.dry_run {
+            let mut output = Vec::new();
+            output.write_all(file.cmd.as_bytes())?;
+            for arg in args {
+                output.write_all(b" ")?;
+                output.write_all(arg.as_bytes())?;
+            }
+            output.write_all(b"\n\n")?;
+            output.write_all(data)?;
+            return Ok(RunRes::new(None, output));
+        }
+
+        file.write(&path, data)?;
+
+        info!("Running {} {:?}", file.cmd, args);
+        let child = target_command(ctx, &file.cmd, &args).spawn();
+        let res = child.map_err(Error::from).and_then(|child| {
+            wait_child(
+                child,
+                None,
+                file.timeout.map(|x| Duration::from_millis(x as u64)),
+            )
+        });
+        match res {
+            Ok(res) => Ok(RunRes {
+                file: Some(path),
+                ..res.into()
+            }),
+            Err(err) => {
+                file.done(&path, false)?;
+                Err(err)
+            }
+        }
+    } else {
+        Err(Error::UnsupportedCommandRunner)
+    }
+}
+
+fn remove(path: &Path) {
+    if let Err(err) = std::fs::remove_file(path) {
+        debug!("Unable to remove {:?}: {}", path, err);
+    }
+}
+
+/// Expect the result like every other runner and remove the input file
+/// unless the result is interesting
+pub fn file_command_expect(ctx: &Context, res: RunRes) -> FResult<ExecRes> {
+    let mut res = default_command_expect(ctx, res)?;
+    let Some(CommandRunnerKind::File(file)) = ctx.runner.as_ref().map(|x| &x.kind) else {
+        return Ok(res);
+    };
+    if let Some(path) = &res.file {
+        let keep = matches!(
+            res.fmt,
+            OutputFmt::Expected | OutputFmt::Crash | OutputFmt::Hang
+        );
+        file.done(path, keep)?;
+        if keep {
+            info!("Keeping {:?}", path)
+        } else {
+            res.file = None;
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use crate::core::{
+        rand::Rand,
+        runner::{temp_path, CommandRunner, CommandRunnerKind},
+        transform::{Context, Expect, IterState, OutputFmt},
+    };
+
+    use super::{file_command_expect, file_command_runner, FileDrop, DEFAULT_FILE_NAME};
+
+    #[test]
+    fn file_names() {
+        let mut file = FileDrop {
+            cmd: "cat".into(),
+            cmd_args: vec!["@@".into()],
+            cmd_arg_target: "OXIFUZZ".into(),
+            file_target: "@@".into(),
+            timeout: None,
+            dir: PathBuf::from("/tmp/drop"),
+            extension: Some(".png".into()),
+            name: DEFAULT_FILE_NAME.into(),
+            files: Default::default(),
+        };
+        assert_eq!(
+            PathBuf::from(format!("/tmp/drop/oxifuzz-{}-7.png", std::process::id())),
+            file.path(b"data", 7)
+        );
+
+        file.extension = None;
+        file.name = "{hash}".into();
+        assert_eq!(
+            PathBuf::from(
+                "/tmp/drop/3a6eb0790f39ac87c94f3856b2dd2c5d110e6811602261a9a923d3bb23adc8b7"
+            ),
+            file.path(b"data", 7)
+        );
+    }
+
+    #[test]
+    fn keep_and_remove() {
+        let dir = temp_path("drop");
+        std::fs::create_dir(&dir).unwrap();
+        let mut ctx = Context::default();
+        ctx.runner = Some(CommandRunner {
+            kind: CommandRunnerKind::File(FileDrop {
+                cmd: "cat".into(),
+                cmd_args: vec!["@@".into()],
+                cmd_arg_target: "OXIFUZZ".into(),
+                file_target: "@@".into(),
+                timeout: None,
+                dir: dir.to_owned(),
+                extension: None,
+                name: "{hash}".into(),
+                files: Default::default(),
+            }),
+            on_run: file_command_runner,
+            on_expect: file_command_expect,
+        });
+        let mut rand = Rand::from_seed(1);
+        let mut run = |ctx: &Context, data: &str| {
+            let runner = ctx.runner.as_ref().unwrap();
+            let res = (runner.on_run)(
+                ctx,
+                &runner.kind,
+                &data.into(),
+                &mut IterState::new(0, &mut rand),
+            )
+            .unwrap();
+            (runner.on_expect)(ctx, res).unwrap()
+        };
+
+        ctx.expect = vec![Expect::Contains(b"crash".to_vec())];
+        let res = run(&ctx, "crash");
+        assert_eq!(OutputFmt::Expected, res.fmt);
+        let kept = res.file.unwrap();
+        assert_eq!(b"crash".to_vec(), std::fs::read(&kept).unwrap());
+
+        // a later uninteresting run of the same input does not remove the finding
+        ctx.expect = vec![Expect::Contains(b"nothing".to_vec())];
+        let res = run(&ctx, "crash");
+        assert_eq!(None, res.file);
+        assert!(kept.exists());
+
+        let res = run(&ctx, "other");
+        assert_eq!(None, res.file);
+        assert_eq!(1, std::fs::read_dir(&dir).unwrap().count());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
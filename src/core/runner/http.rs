@@ -0,0 +1,601 @@
+use std::{
+    io::Write,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use isahc::{
+    config::{Configurable, RedirectPolicy, SslOption, VersionNegotiation},
+    cookies::CookieJar,
+    http::Method,
+    prelude::*,
+    Body, HttpClient, Request, Response,
+};
+use log::{debug, info};
+
+use crate::core::{
+    config::{Config, HttpMethod},
+    error::{Error, FResult},
+    transform::{Context, IterState, Word, DEFAULT_USER_AGENT},
+};
+
+use super::{replace_fuzz, replace_fuzz_bytes, CommandRunnerKind, RunRes};
+
+/// Headers of a raw request that are computed by the client instead
+const SKIPPED_HEADERS: &[&str] = &["content-length", "transfer-encoding"];
+
+/// Header names and values
+pub type Headers = Vec<(String, String)>;
+
+/// A http target
+#[derive(Clone)]
+pub struct Http {
+    pub url: Option<String>,
+    pub headers: Vec<String>,
+    pub method: HttpMethod,
+    pub no_headers: bool,
+    pub cmd_arg_target: String,
+    /// A raw HTTP/1.1 request that is used instead of url and method.
+    /// headers replace its headers of the same name
+    pub request: Option<Word>,
+    pub session: Session,
+    pub client: HttpClient,
+}
+
+/// A request that authenticates the session before the first fuzz request
+#[derive(Clone)]
+pub struct Login {
+    /// Raw HTTP/1.1 request
+    pub request: String,
+    /// Extracts the token from the response. The first capture group is used if there is one
+    pub token: Option<regex::Regex>,
+    /// The header the token is sent in. {token} is replaced with the token
+    pub header: String,
+}
+
+/// State that is shared by all requests of a run, including requests of other workers
+#[derive(Clone, Default)]
+pub struct Session {
+    pub cookies: Option<CookieJar>,
+    /// Follow up to this many redirects
+    pub max_redirects: Option<u32>,
+    pub login: Option<Login>,
+    /// Headers obtained by the login request, None until the login happened
+    headers: Arc<Mutex<Option<Headers>>>,
+}
+
+impl Session {
+    pub fn new(cookies: bool, max_redirects: Option<u32>, login: Option<Login>) -> Self {
+        Self {
+            // the login usually hands out a session cookie
+            cookies: if cookies || login.is_some() {
+                Some(CookieJar::new())
+            } else {
+                None
+            },
+            max_redirects,
+            login,
+            headers: Default::default(),
+        }
+    }
+}
+
+impl From<HttpMethod> for Method {
+    fn from(value: HttpMethod) -> Self {
+        match value {
+            HttpMethod::Get => Method::GET,
+            HttpMethod::Head => Method::HEAD,
+            HttpMethod::Post => Method::POST,
+            HttpMethod::Put => Method::PUT,
+            HttpMethod::Delete => Method::DELETE,
+        }
+    }
+}
+
+/// The parts of a http response
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct HttpRes {
+    pub status: u16,
+    pub headers: Headers,
+    pub body: Word,
+}
+
+impl HttpRes {
+    /// Values of all headers with this name
+    pub fn header<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.headers
+            .iter()
+            .filter(move |(x, _)| x.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The Content-Length header or the length of the body if there is none
+    pub fn content_length(&self) -> usize {
+        self.header("content-length")
+            .next()
+            .and_then(|x| x.trim().parse().ok())
+            .unwrap_or(self.body.len())
+    }
+}
+
+/// A request that is ready to be sent
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct RawRequest {
+    pub method: String,
+    pub url: String,
+    pub headers: Headers,
+    pub body: Word,
+}
+
+impl RawRequest {
+    /// Parse a raw HTTP/1.1 request.
+    /// Request targets that are not absolute urls are resolved against base
+    /// or the Host header if no base is given
+    pub fn parse(request: &[u8], base: Option<&str>) -> FResult<Self> {
+        let (head, body) = split_head(request);
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+
+        let mut request_line = lines
+            .next()
+            .ok_or(Error::InvalidHttpRequest)?
+            .split_whitespace();
+        let method = request_line
+            .next()
+            .ok_or(Error::InvalidHttpRequest)?
+            .to_owned();
+        let target = request_line.next().ok_or(Error::InvalidHttpRequest)?;
+
+        let mut headers = Vec::new();
+        for line in lines {
+            let (name, value) = line.split_once(':').ok_or(Error::InvalidHttpRequest)?;
+            let name = name.trim();
+            if !SKIPPED_HEADERS.contains(&name.to_lowercase().as_str()) {
+                headers.push((name.to_owned(), value.trim().to_owned()));
+            }
+        }
+
+        let url = if target.contains("://") {
+            target.to_owned()
+        } else if let Some(base) = base {
+            format!("{}{}", base.trim_end_matches('/'), target)
+        } else {
+            let host = headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("host"))
+                .map(|(_, value)| value)
+                .ok_or(Error::InvalidHttpRequest)?;
+            format!("http://{host}{target}")
+        };
+
+        Ok(Self {
+            method,
+            url,
+            headers,
+            body: body.to_vec(),
+        })
+    }
+}
+
+/// Split a request into head and body at the first empty line
+fn split_head(request: &[u8]) -> (&[u8], &[u8]) {
+    for (i, window) in request.windows(2).enumerate() {
+        if window == b"\n\n" {
+            return (&request[..i], &request[i + 2..]);
+        }
+        if window == b"\n\r" && request.get(i + 2) == Some(&b'\n') {
+            return (&request[..i], &request[i + 3..]);
+        }
+    }
+    (request, &[])
+}
+
+impl Http {
+    /// Send a request with the session's cookies and redirect policy
+    fn send(&self, request: RawRequest) -> FResult<Response<Body>> {
+        let method =
+            Method::from_bytes(request.method.as_bytes()).map_err(|_| Error::InvalidHttpRequest)?;
+        let mut builder = Request::builder().method(method).uri(request.url);
+        if !request
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("user-agent"))
+        {
+            builder = builder.header("User-Agent", DEFAULT_USER_AGENT);
+        }
+        for (name, value) in request.headers {
+            builder = builder.header(name, value);
+        }
+        Ok(self.client.send(builder.body(request.body)?)?)
+    }
+
+    /// Build the client that is shared by all requests.
+    /// The client keeps a pool of connections and applies the session's cookies and redirect policy
+    pub fn client(cfg: &Config, session: &Session) -> FResult<HttpClient> {
+        let mut builder = HttpClient::builder()
+            .timeout(Duration::from_millis(cfg.http_timeout.unwrap_or(30) as u64))
+            .max_connections_per_host(cfg.max_connections_per_host);
+        if let Some(cookies) = &session.cookies {
+            builder = builder.cookie_jar(cookies.clone());
+        }
+        if let Some(max_redirects) = session.max_redirects {
+            builder = builder.redirect_policy(RedirectPolicy::Limit(max_redirects));
+        }
+        if let Some(proxy) = &cfg.proxy {
+            builder = builder.proxy(Some(proxy.parse().map_err(|_| Error::ArgError)?));
+        }
+        if cfg.insecure {
+            builder = builder.ssl_options(
+                SslOption::DANGER_ACCEPT_INVALID_CERTS
+                    | SslOption::DANGER_ACCEPT_INVALID_HOSTS
+                    | SslOption::DANGER_ACCEPT_REVOKED_CERTS,
+            );
+        }
+        builder = builder.version_negotiation(if cfg.http2_prior_knowledge {
+            VersionNegotiation::http2()
+        } else if cfg.http2 {
+            VersionNegotiation::latest_compatible()
+        } else {
+            VersionNegotiation::http11()
+        });
+        Ok(builder.build()?)
+    }
+
+    /// Headers of the logged in session. The login request is sent once on first use
+    fn session_headers(&self) -> FResult<Headers> {
+        let Some(login) = &self.session.login else {
+            return Ok(vec![]);
+        };
+        let mut headers = self.session.headers.lock().map_err(|_| Error::Unknown)?;
+        if let Some(headers) = headers.as_ref() {
+            return Ok(headers.to_owned());
+        }
+
+        let request = RawRequest::parse(login.request.as_bytes(), self.url.as_deref())?;
+        info!("Logging in with {} {}", request.method, request.url);
+        let mut resp = self.send(request)?;
+        let mut text = format!("{}\n", resp.status());
+        for (name, value) in resp.headers() {
+            text.push_str(&format!(
+                "{}:{}\n",
+                name,
+                String::from_utf8_lossy(value.as_bytes())
+            ));
+        }
+        text.push_str(&resp.text()?);
+
+        let res = login.headers(&text)?;
+        *headers = Some(res.to_owned());
+        Ok(res)
+    }
+}
+
+impl Login {
+    /// The headers of the session from the login response.
+    /// Without a token regex the session only relies on cookies
+    fn headers(&self, response: &str) -> FResult<Headers> {
+        let Some(re) = &self.token else {
+            return Ok(vec![]);
+        };
+        let captures = re.captures(response).ok_or(Error::LoginFailed)?;
+        let token = captures
+            .get(1)
+            .or(captures.get(0))
+            .ok_or(Error::LoginFailed)?
+            .as_str();
+        debug!("Login token {}", token);
+        let header = self.header.replace("{token}", token);
+        let (name, value) = header.split_once(':').ok_or(Error::ArgError)?;
+        Ok(vec![(name.trim().to_owned(), value.trim().to_owned())])
+    }
+}
+
+/// Render the header templates of an iteration
+fn render_headers(ctx: &Context, http: &Http, state: &mut IterState) -> FResult<Headers> {
+    let mut headers = vec![];
+    for header in &http.headers {
+        let header = replace_fuzz(header, &http.cmd_arg_target, ctx, state)?;
+        let split = header.split_once(':').unwrap_or((&header, ""));
+        headers.push((split.0.to_owned(), split.1.to_owned()));
+    }
+    Ok(headers)
+}
+
+/// Render the request of an iteration
+fn render(ctx: &Context, http: &Http, data: &Word, state: &mut IterState) -> FResult<RawRequest> {
+    if let Some(request) = &http.request {
+        let request = replace_fuzz_bytes(request, &http.cmd_arg_target, ctx, state)?;
+        let mut request = RawRequest::parse(&request, http.url.as_deref())?;
+        // a request without a body sends the input instead
+        if request.body.is_empty() {
+            request.body = data.to_owned();
+        }
+        for (name, value) in render_headers(ctx, http, state)? {
+            request
+                .headers
+                .retain(|(x, _)| !x.eq_ignore_ascii_case(&name));
+            request.headers.push((name, value));
+        }
+        Ok(request)
+    } else {
+        let url = http
+            .url
+            .as_ref()
+            .ok_or(Error::InsufficientRunnerConfiguration)?;
+        let url = replace_fuzz(url, &http.cmd_arg_target, ctx, state)?;
+
+        Ok(RawRequest {
+            method: Method::from(http.method).to_string(),
+            url,
+            headers: render_headers(ctx, http, state)?,
+            body: data.to_owned(),
+        })
+    }
+}
+
+pub fn http_command_runner(
+    ctx: &Context,
+    runner: &CommandRunnerKind,
+    data: &Word,
+    state: &mut IterState,
+) -> FResult<RunRes> {
+    if let CommandRunnerKind::Http(http) = runner {
+        let mut request = render(ctx, http, data, state)?;
+        if !ctx.dry_run {
+            // headers of the session replace headers of the template
+            for (name, value) in http.session_headers()? {
+                request
+                    .headers
+                    .retain(|(x, _)| !x.eq_ignore_ascii_case(&name));
+                request.headers.push((name, value));
+            }
+        }
+
+        if ctx.dry_run && http.request.is_none() {
+            let mut output = Vec::new();
+            output.write_all(request.url.as_bytes())?;
+            if !request.headers.is_empty() {
+                output.write_all(b"\n\n")?;
+                for (name, value) in &request.headers {
+                    output.write_all(name.as_bytes())?;
+                    output.write_all(b":")?;
+                    output.write_all(value.as_bytes())?;
+                    output.write_all(b"\n")?;
+                }
+            }
+            if !data.is_empty() {
+                output.write_all(b"\n\n")?;
+                output.write_all(data)?;
+            }
+            Ok(RunRes::new(None, output))
+        } else if ctx.dry_run {
+            // raw requests are shown with their method
+            let mut output = Vec::new();
+            output.write_all(request.method.as_bytes())?;
+            output.write_all(b" ")?;
+            output.write_all(request.url.as_bytes())?;
+            output.write_all(b"\n")?;
+            for (name, value) in &request.headers {
+                output.write_all(name.as_bytes())?;
+                output.write_all(b":")?;
+                output.write_all(value.as_bytes())?;
+                output.write_all(b"\n")?;
+            }
+            if !request.body.is_empty() {
+                output.write_all(b"\n")?;
+                output.write_all(&request.body)?;
+            }
+            Ok(RunRes::new(None, output))
+        } else {
+            info!(
+                "Running {} {} {:?}",
+                request.method, request.url, request.headers
+            );
+
+            let mut resp = http.send(request)?;
+            let status = resp.status().as_u16();
+            let res = HttpRes {
+                status,
+                headers: resp
+                    .headers()
+                    .iter()
+                    .map(|(name, value)| {
+                        (
+                            name.as_str().to_owned(),
+                            String::from_utf8_lossy(value.as_bytes()).to_string(),
+                        )
+                    })
+                    .collect(),
+                body: resp.bytes()?,
+            };
+
+            let mut output = Vec::new();
+
+            if !http.no_headers {
+                output.write_all(res.status.to_string().as_bytes())?;
+                output.write_all(b"\n")?;
+                for (name, value) in &res.headers {
+                    output.write_all(name.as_bytes())?;
+                    output.write_all(b":")?;
+                    output.write_all(value.as_bytes())?;
+                    output.write_all(b"\n")?;
+                }
+                if !res.headers.is_empty() {
+                    output.write_all(b"\n\n")?;
+                }
+            }
+
+            output.write_all(&res.body)?;
+            Ok(RunRes {
+                http: Some(res),
+                ..RunRes::new(Some(status.into()), output)
+            })
+        }
+    } else {
+        Err(Error::UnsupportedCommandRunner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    use crate::core::{
+        config::Config,
+        rand::Rand,
+        runner::{temp_path, CommandRunner},
+        transform::{Context, IterState},
+    };
+
+    use super::{Http, Login, RawRequest, Session};
+
+    /// Send a GET request and return the first line the server received.
+    /// request is called with the address of the server and returns the config and url
+    fn first_line(request: impl FnOnce(&str) -> (Config, String)) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            let mut buffer = [0; 256];
+            while !received.windows(2).any(|x| x == b"\r\n") {
+                match stream.read(&mut buffer).unwrap() {
+                    0 => break,
+                    n => received.extend_from_slice(&buffer[..n]),
+                }
+            }
+            let _ = stream.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+            let received = String::from_utf8_lossy(&received).to_string();
+            received.lines().next().unwrap_or_default().to_owned()
+        });
+
+        let (mut cfg, url) = request(&addr);
+        cfg.http_timeout = Some(2000);
+        let client = Http::client(&cfg, &Session::default()).unwrap();
+        // HTTP/2 clients cannot read the reply
+        let _ = client.get(url);
+        server.join().unwrap()
+    }
+
+    #[test]
+    fn clients() {
+        let direct = |http2: bool, http2_prior_knowledge: bool| {
+            first_line(|addr| {
+                let cfg = Config {
+                    http2,
+                    http2_prior_knowledge,
+                    ..Default::default()
+                };
+                (cfg, format!("http://{addr}/a"))
+            })
+        };
+        assert_eq!("GET /a HTTP/1.1", direct(false, false));
+        // plain text connections only use HTTP/2 with prior knowledge
+        assert_eq!("GET /a HTTP/1.1", direct(true, false));
+        assert_eq!("PRI * HTTP/2.0", direct(false, true));
+
+        let proxied = first_line(|addr| {
+            let cfg = Config {
+                proxy: Some(format!("http://{addr}")),
+                ..Default::default()
+            };
+            (cfg, "http://fuzz.invalid/a".into())
+        });
+        assert_eq!("GET http://fuzz.invalid/a HTTP/1.1", proxied);
+    }
+
+    #[test]
+    fn raw_requests() {
+        let request = b"PURGE /cache?id=1 HTTP/1.1\r\nHost: example.com\r\nContent-Length: 3\r\nX-Test: a:b\r\n\r\nbody";
+        assert_eq!(
+            RawRequest {
+                method: "PURGE".into(),
+                url: "http://example.com/cache?id=1".into(),
+                headers: vec![
+                    ("Host".into(), "example.com".into()),
+                    ("X-Test".into(), "a:b".into())
+                ],
+                body: b"body".to_vec(),
+            },
+            RawRequest::parse(request, None).unwrap()
+        );
+
+        let request = b"GET /a HTTP/1.1\nHost: example.com\n";
+        let parsed = RawRequest::parse(request, Some("https://localhost:8443/")).unwrap();
+        assert_eq!("https://localhost:8443/a", parsed.url);
+        assert!(parsed.body.is_empty());
+
+        assert!(RawRequest::parse(b"GET /a HTTP/1.1\n", None).is_err());
+    }
+
+    #[test]
+    fn raw_request_headers() {
+        let path = temp_path("request");
+        std::fs::write(
+            &path,
+            "POST /a HTTP/1.1\nHost: example.com\nX-Token: old\n\nbody",
+        )
+        .unwrap();
+        let cfg = Config {
+            http_request: Some(path.to_owned()),
+            header: vec!["x-token:new".into(), "X-Extra:1".into()],
+            ..Default::default()
+        };
+        let mut ctx = Context::default();
+        ctx.dry_run = true;
+        ctx.runner = CommandRunner::http_runner(&cfg).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let runner = ctx.runner.as_ref().unwrap();
+        let mut rand = Rand::from_seed(1);
+        let res = runner
+            .run(&ctx, &vec![], &mut IterState::new(0, &mut rand))
+            .unwrap();
+        // command line headers replace headers of the raw request
+        assert_eq!(
+            "POST http://example.com/a\nHost:example.com\nx-token:new\nX-Extra:1\n\nbody",
+            String::from_utf8_lossy(&res.out)
+        );
+    }
+
+    #[test]
+    fn login_tokens() {
+        let response = "200 OK\nSet-Cookie:sid=1\n{\"token\": \"abc.def\", \"expires\": 60}";
+        let login = |token: Option<&str>, header: &str| Login {
+            request: String::new(),
+            token: token.map(|x| regex::Regex::new(x).unwrap()),
+            header: header.into(),
+        };
+
+        // the first capture group is the token
+        assert_eq!(
+            vec![("Authorization".into(), "Bearer abc.def".into())],
+            login(
+                Some(r#""token": "([^"]+)""#),
+                "Authorization: Bearer {token}"
+            )
+            .headers(response)
+            .unwrap()
+        );
+        // or the whole match without groups
+        assert_eq!(
+            vec![("X-Session".into(), "sid=1".into())],
+            login(Some("sid=[0-9]+"), "X-Session:{token}")
+                .headers(response)
+                .unwrap()
+        );
+        assert!(login(None, "Authorization: Bearer {token}")
+            .headers(response)
+            .unwrap()
+            .is_empty());
+        assert!(login(Some("csrf=(\\w+)"), "X-Csrf: {token}")
+            .headers(response)
+            .is_err());
+        assert!(login(Some("sid"), "no header {token}")
+            .headers(response)
+            .is_err());
+    }
+}
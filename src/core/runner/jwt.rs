@@ -3,8 +3,7 @@ use std::io::Read;
 use crate::core::{
     config::{Config, SignatureConfig},
     error::{Error, FResult},
-    rand::Rand,
-    transform::{Context, Word},
+    transform::{Context, IterState, Word},
 };
 use base64::{engine::general_purpose, Engine as _};
 use hmac::{Hmac, Mac};
@@ -13,7 +12,7 @@ use openssl::pkey::PKey;
 use openssl::sign::Signer;
 use sha2::Sha256;
 
-use super::{replace_fuzz, CommandRunnerKind};
+use super::{replace_fuzz, CommandRunnerKind, RunRes};
 
 /// The signature algorithm used to sign a JWT token
 #[derive(Clone, Default)]
@@ -66,7 +65,7 @@ impl Signature {
         match self {
             Signature::HmacSha256 { secret } => {
                 type HmacSha256 = Hmac<Sha256>;
-                let mut mac = HmacSha256::new_from_slice(&secret)?;
+                let mut mac = HmacSha256::new_from_slice(secret)?;
                 mac.update(data.as_bytes());
 
                 Ok(Some(
@@ -100,14 +99,14 @@ pub fn jwt_command_runner(
     ctx: &Context,
     runner: &CommandRunnerKind,
     data: &Word,
-    rand: &mut Rand,
-) -> FResult<(Option<i32>, Word)> {
+    state: &mut IterState,
+) -> FResult<RunRes> {
     if let CommandRunnerKind::Jwt(jwt) = &runner {
         let encoded_header = general_purpose::URL_SAFE_NO_PAD.encode(&replace_fuzz(
             &jwt.header,
             &jwt.cmd_arg_target,
             ctx,
-            rand,
+            state,
         )?);
         let encoded_payload = general_purpose::URL_SAFE_NO_PAD.encode(data);
 
@@ -121,7 +120,7 @@ pub fn jwt_command_runner(
             encoded_without_signature
         };
 
-        Ok((None, encoded_final.as_bytes().iter().map(|x| *x).collect()))
+        Ok(RunRes::new(None, encoded_final.as_bytes().to_vec()))
     } else {
         Err(Error::UnsupportedCommandRunner)
     }
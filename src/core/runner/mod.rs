@@ -1,34 +1,113 @@
+pub mod crash;
+pub mod file;
+pub mod http;
 pub mod jwt;
+pub mod server;
+pub mod socket;
+pub mod tcp;
+pub mod udp;
+pub mod unix;
 
 use std::{
-    io::{BufReader, BufWriter, Read, Write},
-    process::{Command, Stdio},
-    time::Duration,
+    io::{ErrorKind, Read, Write},
+    os::unix::process::CommandExt,
+    path::PathBuf,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
 };
 
-use crate::core::transform::DEFAULT_USER_AGENT;
-
-use self::jwt::{jwt_command_runner, Jwt};
+use crate::core::coverage::SHM_ENV_VAR;
+
+use self::{
+    crash::Crash,
+    file::{file_command_expect, file_command_runner, FileDrop},
+    http::{http_command_runner, Http, HttpRes, Login, Session},
+    jwt::{jwt_command_runner, Jwt},
+    server::{ExecMode, Server},
+    socket::{ConnErr, SocketOpts},
+    tcp::{tcp_command_runner, Tcp},
+    udp::{udp_command_runner, Udp},
+    unix::{unix_command_runner, Unix},
+};
 
 use super::{
-    config::{Config, HttpMethod},
+    config::Config,
     error::{Error, FResult},
-    rand::Rand,
-    transform::{Context, ExecRes, ExitCodes, OutputFmt, Word},
+    transform::{Context, ExecRes, ExitCodes, IterState, OutputFmt, Substitution, Target, Word},
 };
 
 use log::{error, info};
 
+/// The result of a single command execution
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct RunRes {
+    pub exit_code: Option<i32>,
+    pub out: Word,
+    pub crash: Option<Crash>,
+    /// The command did not finish before its timeout
+    pub timed_out: bool,
+    pub err: Word,
+    /// The socket target refused or reset the connection
+    pub conn_err: Option<ConnErr>,
+    /// The file the input was written to
+    pub file: Option<PathBuf>,
+    /// The structured response of a http target. exit_code holds the status code
+    pub http: Option<HttpRes>,
+    /// Wall-clock time of the run
+    pub duration: Duration,
+    /// The words that were substituted into the input and the runner's templates
+    pub substitutions: Vec<Substitution>,
+}
+
+/// The raw result of a shell target
+#[derive(Default, Debug)]
+pub struct ChildRes {
+    /// None if the target is still alive
+    pub status: Option<ExitStatus>,
+    pub stdout: Word,
+    pub stderr: Word,
+    pub timed_out: bool,
+}
+
+impl From<ChildRes> for RunRes {
+    fn from(value: ChildRes) -> Self {
+        let stdout = String::from_utf8_lossy(&value.stdout);
+        RunRes {
+            exit_code: value.status.and_then(|x| x.code()),
+            out: stdout.trim_end().into(),
+            // the timeout kills the target which is not a crash
+            crash: if value.timed_out {
+                None
+            } else {
+                Crash::detect(value.status.as_ref(), &value.stderr)
+            },
+            timed_out: value.timed_out,
+            err: value.stderr,
+            ..Default::default()
+        }
+    }
+}
+
+impl RunRes {
+    pub fn new(exit_code: Option<i32>, out: Word) -> Self {
+        Self {
+            exit_code,
+            out,
+            ..Default::default()
+        }
+    }
+}
+
 /// Function that runs a command and returns an exit code and the output of the command
 pub type CommandRunnerFn = fn(
     ctx: &Context,
     runner: &CommandRunnerKind,
     data: &Word,
-    rand: &mut Rand,
-) -> FResult<(Option<i32>, Word)>;
+    state: &mut IterState,
+) -> FResult<RunRes>;
 
-pub type CommandExpectFn =
-    fn(ctx: &Context, exit_code: Option<i32>, data: &Word) -> FResult<ExecRes>;
+pub type CommandExpectFn = fn(ctx: &Context, res: RunRes) -> FResult<ExecRes>;
 
 #[derive(Clone)]
 pub enum CommandRunnerKind {
@@ -37,20 +116,73 @@ pub enum CommandRunnerKind {
         cmd_args: Vec<String>,
         cmd_arg_target: String,
         no_stdin: bool,
+        timeout: Option<u32>,
+        mode: ExecMode,
     },
-    Http {
-        url: String,
-        headers: Vec<String>,
-        method: HttpMethod,
-        no_headers: bool,
-        timeout: u32,
-        cmd_arg_target: String,
-    },
+    Http(Http),
     Jwt(Jwt),
+    File(FileDrop),
+    Tcp(Tcp),
+    Udp(Udp),
+    Unix(Unix),
     Output,
     None,
 }
 
+impl CommandRunnerKind {
+    /// Name of the runner kind
+    pub fn name(&self) -> &'static str {
+        match self {
+            CommandRunnerKind::Shell { .. } => "shell",
+            CommandRunnerKind::Http(_) => "http",
+            CommandRunnerKind::Jwt(_) => "jwt",
+            CommandRunnerKind::File(_) => "file",
+            CommandRunnerKind::Tcp(_) => "tcp",
+            CommandRunnerKind::Udp(_) => "udp",
+            CommandRunnerKind::Unix(_) => "unix",
+            CommandRunnerKind::Output => "output",
+            CommandRunnerKind::None => "none",
+        }
+    }
+
+    /// All templates the runner replaces targets in and the placeholder of the default target,
+    /// in the order the runner applies them
+    pub fn templates(&self) -> Vec<(&[u8], &str)> {
+        match self {
+            CommandRunnerKind::Shell {
+                cmd_args,
+                cmd_arg_target,
+                ..
+            } => cmd_args
+                .iter()
+                .map(|x| (x.as_bytes(), cmd_arg_target.as_str()))
+                .collect(),
+            CommandRunnerKind::Http(http) => {
+                // the url is only a base for raw requests and is not rendered
+                let base: Vec<&[u8]> = if let Some(request) = &http.request {
+                    vec![request]
+                } else {
+                    http.url.iter().map(|x| x.as_bytes()).collect()
+                };
+                base.into_iter()
+                    .chain(http.headers.iter().map(|x| x.as_bytes()))
+                    .map(|x| (x, http.cmd_arg_target.as_str()))
+                    .collect()
+            }
+            CommandRunnerKind::Jwt(jwt) => vec![(jwt.header.as_bytes(), &jwt.cmd_arg_target)],
+            CommandRunnerKind::File(file) => file
+                .cmd_args
+                .iter()
+                .map(|x| (x.as_bytes(), file.cmd_arg_target.as_str()))
+                .collect(),
+            CommandRunnerKind::Tcp(tcp) => vec![(tcp.addr.as_bytes(), &tcp.cmd_arg_target)],
+            CommandRunnerKind::Udp(udp) => vec![(udp.addr.as_bytes(), &udp.cmd_arg_target)],
+            CommandRunnerKind::Unix(unix) => vec![(unix.path.as_bytes(), &unix.cmd_arg_target)],
+            CommandRunnerKind::Output | CommandRunnerKind::None => vec![],
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct CommandRunner {
     pub(crate) kind: CommandRunnerKind,
@@ -67,6 +199,8 @@ impl CommandRunner {
                     cmd_args: cfg.cmd_args()?.unwrap_or(vec![]),
                     cmd_arg_target: cfg.exec_target.to_owned(),
                     no_stdin: cfg.no_stdin,
+                    timeout: cfg.exec_timeout,
+                    mode: cfg.exec_mode,
                 },
                 on_run: shell_command_runner,
                 on_expect: default_command_expect,
@@ -77,6 +211,19 @@ impl CommandRunner {
         }
     }
 
+    pub fn file_runner(cfg: &Config) -> FResult<Option<Self>> {
+        if let Some(file) = FileDrop::from_cfg(cfg)? {
+            Ok(Some(Self {
+                kind: CommandRunnerKind::File(file),
+                on_run: file_command_runner,
+                on_expect: file_command_expect,
+            }))
+        } else {
+            error!("Command file runner configured without a command!");
+            Err(Error::InsufficientRunnerConfiguration)
+        }
+    }
+
     pub fn output_runner(_cfg: &Config) -> FResult<Option<Self>> {
         Ok(Some(Self {
             kind: CommandRunnerKind::Output,
@@ -86,16 +233,39 @@ impl CommandRunner {
     }
 
     pub fn http_runner(cfg: &Config) -> FResult<Option<Self>> {
-        if let Some(url) = &cfg.url {
+        let request = if let Some(path) = &cfg.http_request {
+            Some(std::fs::read(path)?)
+        } else {
+            None
+        };
+
+        let login = if let Some(path) = &cfg.login_request {
+            Some(Login {
+                request: std::fs::read_to_string(path)?,
+                token: cfg
+                    .login_token
+                    .as_ref()
+                    .map(|x| regex::Regex::new(x).map_err(|_| Error::InvalidRegex))
+                    .transpose()?,
+                header: cfg.login_header.to_owned(),
+            })
+        } else {
+            None
+        };
+
+        if cfg.url.is_some() || request.is_some() {
+            let session = Session::new(cfg.http_cookies, cfg.max_redirects, login);
             Ok(Some(Self {
-                kind: CommandRunnerKind::Http {
-                    url: url.to_owned(),
+                kind: CommandRunnerKind::Http(Http {
+                    url: cfg.url.to_owned(),
                     headers: cfg.header.to_owned(),
                     method: cfg.http_method.unwrap_or_default(),
                     no_headers: cfg.no_headers,
-                    timeout: cfg.http_timeout.unwrap_or(30),
                     cmd_arg_target: cfg.exec_target.to_owned(),
-                },
+                    request,
+                    client: Http::client(cfg, &session)?,
+                    session,
+                }),
                 on_run: http_command_runner,
                 on_expect: default_command_expect,
             }))
@@ -111,10 +281,8 @@ impl CommandRunner {
             let mut buffer = Vec::new();
             f.read_to_end(&mut buffer)?;
             Some(String::from_utf8_lossy(&buffer).to_string())
-        } else if let Some(data) = &cfg.jwt_header {
-            Some(data.to_owned())
         } else {
-            None
+            cfg.jwt_header.to_owned()
         };
 
         if let Some(header) = &header {
@@ -134,11 +302,73 @@ impl CommandRunner {
         }
     }
 
+    pub fn tcp_runner(cfg: &Config) -> FResult<Option<Self>> {
+        if let Some(addr) = &cfg.addr {
+            Ok(Some(Self {
+                kind: CommandRunnerKind::Tcp(Tcp {
+                    addr: addr.to_owned(),
+                    opts: SocketOpts::from_cfg(cfg),
+                    cmd_arg_target: cfg.exec_target.to_owned(),
+                }),
+                on_run: tcp_command_runner,
+                on_expect: default_command_expect,
+            }))
+        } else {
+            error!("Command tcp runner configured without an address!");
+            Err(Error::InsufficientRunnerConfiguration)
+        }
+    }
+
+    pub fn udp_runner(cfg: &Config) -> FResult<Option<Self>> {
+        if let Some(addr) = &cfg.addr {
+            Ok(Some(Self {
+                kind: CommandRunnerKind::Udp(Udp {
+                    addr: addr.to_owned(),
+                    opts: SocketOpts::from_cfg(cfg),
+                    cmd_arg_target: cfg.exec_target.to_owned(),
+                }),
+                on_run: udp_command_runner,
+                on_expect: default_command_expect,
+            }))
+        } else {
+            error!("Command udp runner configured without an address!");
+            Err(Error::InsufficientRunnerConfiguration)
+        }
+    }
+
+    pub fn unix_runner(cfg: &Config) -> FResult<Option<Self>> {
+        if let Some(path) = &cfg.unix_socket {
+            Ok(Some(Self {
+                kind: CommandRunnerKind::Unix(Unix {
+                    path: path.to_owned(),
+                    kind: cfg.unix_kind,
+                    opts: SocketOpts::from_cfg(cfg),
+                    cmd_arg_target: cfg.exec_target.to_owned(),
+                }),
+                on_run: unix_command_runner,
+                on_expect: default_command_expect,
+            }))
+        } else {
+            error!("Command unix runner configured without a socket path!");
+            Err(Error::InsufficientRunnerConfiguration)
+        }
+    }
+
     fn auto_select_runner(cfg: &Config) -> FResult<Option<Self>> {
-        if cfg.exec.is_some() {
+        if cfg
+            .cmd_args()?
+            .map(|x| x.iter().any(|x| x.contains(&cfg.file_target)))
+            .unwrap_or(false)
+        {
+            Self::file_runner(cfg)
+        } else if cfg.exec.is_some() {
             Self::shell_runner(cfg)
-        } else if cfg.url.is_some() {
+        } else if cfg.url.is_some() || cfg.http_request.is_some() {
             Self::http_runner(cfg)
+        } else if cfg.addr.is_some() {
+            Self::tcp_runner(cfg)
+        } else if cfg.unix_socket.is_some() {
+            Self::unix_runner(cfg)
         } else if cfg.jwt_header.is_some() || cfg.jwt_header_file.is_some() {
             Self::jwt_runner(cfg)
         } else {
@@ -153,43 +383,73 @@ impl CommandRunner {
             super::config::RunnerKindConfig::Output => Self::output_runner(cfg),
             super::config::RunnerKindConfig::Http => Self::http_runner(cfg),
             super::config::RunnerKindConfig::Jwt => Self::jwt_runner(cfg),
+            super::config::RunnerKindConfig::File => Self::file_runner(cfg),
+            super::config::RunnerKindConfig::Tcp => Self::tcp_runner(cfg),
+            super::config::RunnerKindConfig::Udp => Self::udp_runner(cfg),
+            super::config::RunnerKindConfig::Unix => Self::unix_runner(cfg),
         }
     }
 
-    pub fn run(&self, ctx: &Context, data: &Word, rand: &mut Rand) -> FResult<(Option<i32>, Word)> {
-        (self.on_run)(ctx, &self.kind, data, rand)
+    pub fn run(&self, ctx: &Context, data: &Word, state: &mut IterState) -> FResult<RunRes> {
+        let start = Instant::now();
+        let mut res = (self.on_run)(ctx, &self.kind, data, state)?;
+        res.duration = start.elapsed();
+        res.substitutions = state.substitutions.to_owned();
+        Ok(res)
     }
 
-    pub fn expect(&self, ctx: &Context, exit_code: Option<i32>, data: &Word) -> FResult<ExecRes> {
-        (self.on_expect)(ctx, exit_code, data)
+    /// Clean up after a run whose result is not expected, e.g. remove its input file
+    pub fn discard(&self, res: &RunRes) -> FResult<()> {
+        if let (CommandRunnerKind::File(file), Some(path)) = (&self.kind, &res.file) {
+            file.done(path, false)?;
+        }
+        Ok(())
     }
 
-    pub fn run_and_expect(&self, ctx: &Context, data: &Word, rand: &mut Rand) -> FResult<ExecRes> {
-        let (exit_code, data) = self.run(ctx, data, rand)?;
-        self.expect(ctx, exit_code, &data)
+    pub fn expect(&self, ctx: &Context, res: RunRes) -> FResult<ExecRes> {
+        (self.on_expect)(ctx, res)
     }
-}
 
-fn replace_fuzz(x: &str, cmd_arg_target: &str, ctx: &Context, rand: &mut Rand) -> FResult<String> {
-    let mut x = x.to_owned();
-    while x.contains(cmd_arg_target) {
-        x = x.replacen(
-            cmd_arg_target,
-            &String::from_utf8_lossy(ctx.select_word(rand)?),
-            1,
-        );
+    pub fn run_and_expect(
+        &self,
+        ctx: &Context,
+        data: &Word,
+        state: &mut IterState,
+    ) -> FResult<ExecRes> {
+        let res = self.run(ctx, data, state)?;
+        self.expect(ctx, res)
     }
-    Ok(x)
+}
+
+fn replace_fuzz(
+    x: &str,
+    cmd_arg_target: &str,
+    ctx: &Context,
+    state: &mut IterState,
+) -> FResult<String> {
+    let x = replace_fuzz_bytes(x.as_bytes(), cmd_arg_target, ctx, state)?;
+    Ok(String::from_utf8_lossy(&x).to_string())
+}
+
+/// Replace targets in a template that may not be valid utf-8
+fn replace_fuzz_bytes(
+    x: &[u8],
+    cmd_arg_target: &str,
+    ctx: &Context,
+    state: &mut IterState,
+) -> FResult<Word> {
+    let primary = Target::Word(cmd_arg_target.as_bytes().to_vec());
+    ctx.substitute(x, &primary, state)
 }
 
 pub fn output_command_runner(
     _ctx: &Context,
     runner: &CommandRunnerKind,
     data: &Word,
-    _rand: &mut Rand,
-) -> FResult<(Option<i32>, Word)> {
+    _state: &mut IterState,
+) -> FResult<RunRes> {
     if let CommandRunnerKind::Output = runner {
-        Ok((None, data.to_owned()))
+        Ok(RunRes::new(None, data.to_owned()))
     } else {
         Err(Error::UnsupportedCommandRunner)
     }
@@ -199,18 +459,20 @@ pub fn shell_command_runner(
     ctx: &Context,
     runner: &CommandRunnerKind,
     data: &Word,
-    rand: &mut Rand,
-) -> FResult<(Option<i32>, Word)> {
+    state: &mut IterState,
+) -> FResult<RunRes> {
     if let CommandRunnerKind::Shell {
         cmd,
         cmd_args,
         cmd_arg_target,
         no_stdin,
+        timeout,
+        mode,
     } = runner
     {
         let args: Vec<String> = cmd_args
             .iter()
-            .map(|x| replace_fuzz(x, cmd_arg_target, ctx, rand))
+            .map(|x| replace_fuzz(x, cmd_arg_target, ctx, state))
             .try_collect()?;
 
         if ctx.dry_run {
@@ -221,145 +483,181 @@ pub fn shell_command_runner(
                 output.write_all(arg.as_bytes())?;
             }
             output.write_all(data)?;
-            Ok((None, output))
+            Ok(RunRes::new(None, output))
         } else {
             info!("Running {} {:?}", cmd, args);
-            let args: Vec<&str> = args.iter().map(|x| x.as_ref()).collect();
-
-            let mut child = Command::new(cmd)
-                .args(args)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .spawn()?;
-
-            if !no_stdin {
-                let mut child_in = BufWriter::new(child.stdin.as_mut().unwrap());
-                child_in.write_all(data)?;
+            let timeout = timeout.map(|x| Duration::from_millis(x as u64));
+
+            if *mode == ExecMode::Spawn {
+                let child = target_command(ctx, cmd, &args).spawn()?;
+                let input = if *no_stdin { None } else { Some(data) };
+                Ok(wait_child(child, input, timeout)?.into())
+            } else {
+                // the server is started once, so its args cannot change with the input
+                if args != *cmd_args {
+                    error!("Targets in the command args are not supported by {mode:?}");
+                    return Err(Error::ArgError);
+                }
+                let mut server = ctx.server.lock().map_err(|_| Error::Unknown)?;
+                // a fork server that died did not run the input, a new one retries it
+                let mut fresh = false;
+                loop {
+                    if server.is_none() {
+                        *server = Some(Server::start(*mode, target_command(ctx, cmd, &args))?);
+                        fresh = true;
+                    }
+                    let current = server.as_mut().ok_or(Error::Unknown)?;
+                    match current.run(data, timeout) {
+                        Err(Error::ForkServerExited) => {
+                            *server = None;
+                            if fresh {
+                                return Err(Error::ForkServerExited);
+                            }
+                            info!("Fork server exited, restarting");
+                        }
+                        Err(err) => return Err(err),
+                        Ok(res) => {
+                            if current.is_done(&res) {
+                                info!("Target exited, restarting on the next run");
+                                *server = None;
+                            }
+                            return Ok(res.into());
+                        }
+                    }
+                }
             }
-            let exit_code = child.wait()?;
-            let mut child_out = BufReader::new(child.stdout.as_mut().unwrap());
-            let output = std::io::read_to_string(&mut child_out)?;
-
-            Ok((exit_code.code(), output.trim_end().into()))
         }
     } else {
         Err(Error::UnsupportedCommandRunner)
     }
 }
 
-pub fn http_command_runner(
-    ctx: &Context,
-    runner: &CommandRunnerKind,
-    data: &Word,
-    rand: &mut Rand,
-) -> FResult<(Option<i32>, Word)> {
-    if let CommandRunnerKind::Http {
-        url,
-        headers,
-        method,
-        no_headers,
-        timeout,
-        cmd_arg_target,
-    } = runner
-    {
-        let url = replace_fuzz(url, cmd_arg_target, ctx, rand)?;
+/// A unique path in the temp directory
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "oxifuzz-{}-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed),
+        name
+    ))
+}
 
-        let headers: Vec<String> = headers
-            .iter()
-            .map(|x| replace_fuzz(x, cmd_arg_target, ctx, rand))
-            .try_collect()?;
+/// Build the command of a shell target with all of its stdio piped
+fn target_command(ctx: &Context, cmd: &str, args: &[String]) -> Command {
+    let mut command = Command::new(cmd);
+    command
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        // the target gets its own process group so that a timeout kills all of its children
+        .process_group(0);
+    if let Some(coverage) = &ctx.coverage {
+        command.env(SHM_ENV_VAR, coverage.shm_id().to_string());
+    }
+    command
+}
 
-        if ctx.dry_run {
-            let mut output = Vec::new();
-            output.write_all(url.as_bytes())?;
-            if !headers.is_empty() {
-                output.write_all(b"\n\n")?;
-                for header in headers {
-                    output.write_all(header.as_bytes())?;
-                    output.write_all(b"\n")?;
-                }
+/// Write the input to the child and wait for it to exit.
+/// If the child does not exit before the timeout its process group is killed
+fn wait_child(
+    mut child: Child,
+    input: Option<&Word>,
+    timeout: Option<Duration>,
+) -> FResult<ChildRes> {
+    fn read_all(pipe: Option<impl Read + Send + 'static>) -> std::thread::JoinHandle<Word> {
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut buffer);
             }
-            if !data.is_empty() {
-                output.write_all(b"\n\n")?;
-                output.write_all(data)?;
+            buffer
+        })
+    }
+
+    // all pipes are handled in their own threads so that a target that does not read its input
+    // or fills the output pipes cannot block us past the timeout
+    let stdin = child.stdin.take();
+    let input = input.map(|x| x.to_owned());
+    let writer = std::thread::spawn(move || -> std::io::Result<()> {
+        if let (Some(mut stdin), Some(input)) = (stdin, input) {
+            // a target that crashes or exits early may not read all of its input
+            match stdin.write_all(&input) {
+                Err(err) if err.kind() == ErrorKind::BrokenPipe => {}
+                x => x?,
             }
-            Ok((None, output))
-        } else {
-            use isahc::{prelude::*, Request};
-            info!("Running {} {:?}", url, headers);
-
-            let client = match method {
-                HttpMethod::Get => Request::get(url),
-                HttpMethod::Head => Request::head(url),
-                HttpMethod::Post => Request::post(url),
-                HttpMethod::Put => Request::put(url),
-                HttpMethod::Delete => Request::delete(url),
-            };
-            let mut client = client.header("User-Agent", DEFAULT_USER_AGENT);
-
-            for header in headers {
-                let split = header.split_once(':').unwrap_or((&header, ""));
-                client = client.header(split.0.to_owned(), split.1.to_owned());
+        }
+        Ok(())
+    });
+    let stdout = read_all(child.stdout.take());
+    let stderr = read_all(child.stderr.take());
+
+    let start = Instant::now();
+    let mut timed_out = false;
+    let status = match timeout {
+        None => child.wait()?,
+        Some(timeout) => loop {
+            if let Some(status) = child.try_wait()? {
+                break status;
             }
-
-            let mut resp = client
-                .timeout(Duration::from_millis(*timeout as u64))
-                .body(data.to_owned())?
-                .send()?;
-            let status = resp.status();
-
-            let mut output = Vec::new();
-
-            if !no_headers {
-                output.write_all(status.as_str().as_bytes())?;
-                output.write_all(b"\n")?;
-                for header in resp.headers() {
-                    output.write_all(header.0.as_str().as_bytes())?;
-                    output.write_all(b":")?;
-                    output.write_all(header.1.as_bytes())?;
-                    output.write_all(b"\n")?;
-                }
-                if !resp.headers().is_empty() {
-                    output.write_all(b"\n\n")?;
-                }
+            if start.elapsed() >= timeout {
+                info!("Killing {} after {:?}", child.id(), start.elapsed());
+                unsafe { libc::kill(-(child.id() as i32), libc::SIGKILL) };
+                timed_out = true;
+                break child.wait()?;
             }
+            std::thread::sleep(Duration::from_millis(1));
+        },
+    };
 
-            output.write_all(&resp.bytes()?)?;
-            Ok((Some(status.as_u16().into()), output))
-        }
-    } else {
-        Err(Error::UnsupportedCommandRunner)
-    }
+    let join_err = |_| Error::Unknown;
+    writer.join().map_err(join_err)??;
+    let stdout = stdout.join().map_err(join_err)?;
+    let stderr = stderr.join().map_err(join_err)?;
+    Ok(ChildRes {
+        status: Some(status),
+        stdout,
+        stderr,
+        timed_out,
+    })
 }
 
-pub fn default_command_expect(
-    ctx: &Context,
-    exit_code: Option<i32>,
-    data: &Word,
-) -> FResult<ExecRes> {
-    let success_code = if exit_code == Some(0) || exit_code.is_none() {
+pub fn default_command_expect(ctx: &Context, res: RunRes) -> FResult<ExecRes> {
+    // every http response means that the runner succeeded
+    let success_code = if res.exit_code == Some(0) || res.exit_code.is_none() || res.http.is_some()
+    {
         ExitCodes::Success
     } else {
         ExitCodes::RunnerFailed
     };
 
-    if ctx.expect.is_empty() {
-        Ok(ExecRes {
-            exit_code: success_code,
-            out: data.to_owned(),
-            fmt: OutputFmt::None,
-        })
-    } else if ctx.compare_expected(data, exit_code) {
-        Ok(ExecRes {
-            exit_code: success_code,
-            out: data.to_owned(),
-            fmt: OutputFmt::Expected,
-        })
+    let expected = !ctx.expect.is_empty() && ctx.compare_expected(&res);
+    let (exit_code, fmt) = if res.crash.is_some() {
+        (ExitCodes::Crash, OutputFmt::Crash)
+    } else if expected {
+        (success_code, OutputFmt::Expected)
+    } else if res.timed_out {
+        (ExitCodes::Hang, OutputFmt::Hang)
+    } else if res.conn_err.is_some() {
+        (ExitCodes::RunnerFailed, OutputFmt::ConnErr)
+    } else if ctx.expect.is_empty() {
+        (success_code, OutputFmt::None)
     } else {
-        Ok(ExecRes {
-            exit_code: ExitCodes::Failure,
-            out: data.to_owned(),
-            fmt: OutputFmt::NotExpected,
-        })
-    }
+        (ExitCodes::Failure, OutputFmt::NotExpected)
+    };
+
+    Ok(ExecRes {
+        exit_code,
+        out: res.out,
+        fmt,
+        status: res.exit_code,
+        crash: res.crash,
+        timed_out: res.timed_out,
+        err: res.err,
+        conn_err: res.conn_err,
+        file: res.file,
+        duration: res.duration,
+        ..Default::default()
+    })
 }
@@ -0,0 +1,551 @@
+use std::{
+    fs::File,
+    io::{pipe, ErrorKind, PipeReader, PipeWriter, Read, Seek, SeekFrom, Write},
+    os::{
+        fd::AsRawFd,
+        unix::process::{CommandExt, ExitStatusExt},
+    },
+    path::PathBuf,
+    process::{Child, ChildStdin, ChildStdout, Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use log::{debug, info};
+
+use crate::core::{
+    error::{Error, FResult},
+    transform::Word,
+};
+
+use super::{temp_path, ChildRes};
+
+/// Control pipe file descriptor of the AFL fork server
+pub const FORKSRV_FD: i32 = 198;
+
+/// How long a fork server may take to start up
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hello of AFL++ 4.21+ fork servers, the low byte is the protocol version
+const FS_NEW_VERSION: u32 = 0x41464c00;
+const FS_NEW_VERSION_MAX: u32 = 1;
+/// Options of new fork servers
+const FS_NEW_OPT_MAPSIZE: u32 = 0x1;
+const FS_NEW_OPT_SHDMEM_FUZZ: u32 = 0x2;
+const FS_NEW_OPT_AUTODICT: u32 = 0x800;
+
+/// How shell targets are executed
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum ExecMode {
+    /// Spawn a new process for every input
+    #[default]
+    Spawn,
+    /// Use the AFL fork server of an instrumented target
+    ForkServer,
+    /// Keep a single process alive that reads length-prefixed inputs from stdin
+    /// and writes length-prefixed replies to stdout
+    Persistent,
+}
+
+/// A long lived shell target
+pub enum Server {
+    Fork(ForkServer),
+    Persistent(PersistentServer),
+}
+
+impl Server {
+    /// Start a server for the command. The command's stdio is replaced
+    pub fn start(mode: ExecMode, command: Command) -> FResult<Self> {
+        match mode {
+            ExecMode::ForkServer => Ok(Self::Fork(ForkServer::start(command)?)),
+            ExecMode::Persistent => Ok(Self::Persistent(PersistentServer::start(command)?)),
+            ExecMode::Spawn => Err(Error::UnsupportedCommandRunner),
+        }
+    }
+
+    /// Run a single input
+    pub fn run(&mut self, data: &Word, timeout: Option<Duration>) -> FResult<ChildRes> {
+        match self {
+            Server::Fork(x) => x.run(data, timeout),
+            Server::Persistent(x) => x.run(data, timeout),
+        }
+    }
+
+    /// true if the server has to be restarted after a result
+    pub fn is_done(&self, res: &ChildRes) -> bool {
+        match self {
+            // the fork server reports the status of the forked child, not its own
+            Server::Fork(_) => false,
+            Server::Persistent(_) => res.status.is_some() || res.timed_out,
+        }
+    }
+}
+
+/// Wait until fd is readable. Returns false on timeout
+fn poll_readable(fd: &impl AsRawFd, timeout: Option<Duration>) -> FResult<bool> {
+    let mut pollfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout = timeout
+        .map(|x| x.as_millis().min(i32::MAX as u128) as i32)
+        .unwrap_or(-1);
+    loop {
+        let res = unsafe { libc::poll(&mut pollfd, 1, timeout) };
+        if res >= 0 {
+            return Ok(res > 0);
+        }
+        let err = std::io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+}
+
+/// Fill the buffer. Every read waits for data so that a partial write cannot block us past
+/// the timeout. Returns false on timeout
+fn read_exact_timeout(
+    reader: &mut (impl Read + AsRawFd),
+    buffer: &mut [u8],
+    timeout: Option<Duration>,
+) -> FResult<bool> {
+    let start = Instant::now();
+    let mut read = 0;
+    while read < buffer.len() {
+        if !poll_readable(reader, timeout.map(|x| x.saturating_sub(start.elapsed())))? {
+            return Ok(false);
+        }
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Read a little endian u32. Returns None on timeout
+fn read_u32(reader: &mut (impl Read + AsRawFd), timeout: Option<Duration>) -> FResult<Option<u32>> {
+    let mut buffer = [0; 4];
+    Ok(read_exact_timeout(reader, &mut buffer, timeout)?.then(|| u32::from_le_bytes(buffer)))
+}
+
+/// true if the other end of a pipe was closed
+fn is_closed(err: &Error) -> bool {
+    matches!(err, Error::Io(err) if matches!(err.kind(), ErrorKind::UnexpectedEof | ErrorKind::BrokenPipe))
+}
+
+/// A temporary file that is shared with the target and removed on drop
+struct SharedFile {
+    path: PathBuf,
+    file: File,
+}
+
+impl SharedFile {
+    fn new(name: &str) -> FResult<Self> {
+        let path = temp_path(name);
+        // append mode makes the target always write to the end even after truncating
+        let file = File::options()
+            .read(true)
+            .append(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self { path, file })
+    }
+
+    fn stdio(&self) -> FResult<Stdio> {
+        Ok(self.file.try_clone()?.into())
+    }
+
+    fn clear(&mut self) -> FResult<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> FResult<Word> {
+        let mut buffer = Vec::new();
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.read_to_end(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+impl Drop for SharedFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// The AFL fork server protocol.
+/// The target stops before main, forks for every 4 byte request on the control pipe
+/// and reports the child's pid and exit status on the status pipe.
+/// The input is passed via a file that is shared with every forked child as stdin
+pub struct ForkServer {
+    child: Child,
+    control: PipeWriter,
+    status: PipeReader,
+    input: File,
+    input_path: PathBuf,
+    stdout: SharedFile,
+    stderr: SharedFile,
+    was_killed: bool,
+}
+
+impl ForkServer {
+    pub fn start(mut command: Command) -> FResult<Self> {
+        let (control_read, control) = pipe()?;
+        let (status, status_write) = pipe()?;
+
+        let input_path = temp_path("input");
+        let input = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&input_path)?;
+        let stdout = SharedFile::new("stdout")?;
+        let stderr = SharedFile::new("stderr")?;
+
+        let control_fd = control_read.as_raw_fd();
+        let status_fd = status_write.as_raw_fd();
+        command
+            .stdin(Stdio::from(input.try_clone()?))
+            .stdout(stdout.stdio()?)
+            .stderr(stderr.stdio()?);
+        unsafe {
+            command.pre_exec(move || {
+                if libc::dup2(control_fd, FORKSRV_FD) < 0
+                    || libc::dup2(status_fd, FORKSRV_FD + 1) < 0
+                {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let child = command.spawn()?;
+        drop(control_read);
+        drop(status_write);
+
+        let mut server = Self {
+            child,
+            control,
+            status,
+            input,
+            input_path,
+            stdout,
+            stderr,
+            was_killed: false,
+        };
+        match server.handshake() {
+            Ok(true) => {}
+            Ok(false) => return Err(Error::ForkServerHandshake),
+            Err(err) if is_closed(&err) => return Err(Error::ForkServerHandshake),
+            Err(err) => return Err(err),
+        }
+        info!("Fork server {} is up", server.child.id());
+        Ok(server)
+    }
+
+    /// Wait for the hello of the fork server. AFL++ 4.21+ fork servers expect a reply and send
+    /// their options before they are ready. Returns false on timeout
+    fn handshake(&mut self) -> FResult<bool> {
+        let Some(hello) = read_u32(&mut self.status, Some(STARTUP_TIMEOUT))? else {
+            return Ok(false);
+        };
+        let version = hello.wrapping_sub(FS_NEW_VERSION);
+        if !(1..=FS_NEW_VERSION_MAX).contains(&version) {
+            // older fork servers only say hello
+            return Ok(true);
+        }
+
+        self.control.write_all(&(hello ^ u32::MAX).to_le_bytes())?;
+        let Some(options) = read_u32(&mut self.status, Some(STARTUP_TIMEOUT))? else {
+            return Ok(false);
+        };
+        debug!("Fork server version {version} with options {options:#x}");
+        if options & FS_NEW_OPT_SHDMEM_FUZZ != 0 {
+            // inputs are passed on stdin, never in shared memory
+            return Err(Error::ForkServerHandshake);
+        }
+        if options & FS_NEW_OPT_MAPSIZE != 0
+            && read_u32(&mut self.status, Some(STARTUP_TIMEOUT))?.is_none()
+        {
+            return Ok(false);
+        }
+        if options & FS_NEW_OPT_AUTODICT != 0 {
+            let Some(len) = read_u32(&mut self.status, Some(STARTUP_TIMEOUT))? else {
+                return Ok(false);
+            };
+            // the dictionary is not used
+            let mut dict = vec![0; len as usize];
+            if !read_exact_timeout(&mut self.status, &mut dict, Some(STARTUP_TIMEOUT))? {
+                return Ok(false);
+            }
+        }
+        // the options end with the hello
+        match read_u32(&mut self.status, Some(STARTUP_TIMEOUT))? {
+            Some(x) if x == hello => Ok(true),
+            Some(_) => Err(Error::ForkServerHandshake),
+            None => Ok(false),
+        }
+    }
+
+    /// Collect the exit status of the fork server after its pipes were closed.
+    /// The input never ran, so its status is not a result
+    fn exited(&mut self) -> Error {
+        match self.child.wait() {
+            Ok(status) => info!("Fork server {} exited with {}", self.child.id(), status),
+            Err(err) => return err.into(),
+        }
+        Error::ForkServerExited
+    }
+
+    pub fn run(&mut self, data: &Word, timeout: Option<Duration>) -> FResult<ChildRes> {
+        self.input.set_len(0)?;
+        self.input.seek(SeekFrom::Start(0))?;
+        self.input.write_all(data)?;
+        // the forked child shares the file offset with us
+        self.input.seek(SeekFrom::Start(0))?;
+        self.stdout.clear()?;
+        self.stderr.clear()?;
+
+        match self.fork(timeout) {
+            Ok((status, timed_out)) => Ok(ChildRes {
+                status: Some(ExitStatus::from_raw(status as i32)),
+                stdout: self.stdout.read()?,
+                stderr: self.stderr.read()?,
+                timed_out,
+            }),
+            Err(err) if is_closed(&err) => Err(self.exited()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Let the fork server run a child and return its raw wait status
+    fn fork(&mut self, timeout: Option<Duration>) -> FResult<(u32, bool)> {
+        self.control
+            .write_all(&(self.was_killed as u32).to_le_bytes())?;
+        let pid = read_u32(&mut self.status, Some(STARTUP_TIMEOUT))?
+            .ok_or(Error::ForkServerHandshake)? as i32;
+        debug!("Fork server started {}", pid);
+
+        let start = Instant::now();
+        let mut timed_out = false;
+        let status = match read_u32(&mut self.status, timeout)? {
+            Some(status) => status,
+            None => {
+                info!("Killing {} after {:?}", pid, start.elapsed());
+                unsafe { libc::kill(pid, libc::SIGKILL) };
+                timed_out = true;
+                read_u32(&mut self.status, None)?.ok_or(Error::ForkServerHandshake)?
+            }
+        };
+        self.was_killed = timed_out;
+        Ok((status, timed_out))
+    }
+}
+
+impl Drop for ForkServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.input_path);
+    }
+}
+
+/// A target that stays alive and handles one input after another.
+/// Every input is written to stdin as a little endian u32 length followed by the data.
+/// The target answers with the same framing on stdout
+pub struct PersistentServer {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: ChildStdout,
+    stderr: SharedFile,
+}
+
+impl PersistentServer {
+    pub fn start(mut command: Command) -> FResult<Self> {
+        let stderr = SharedFile::new("stderr")?;
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr.stdio()?)
+            .spawn()?;
+        info!("Persistent target {} is up", child.id());
+
+        Ok(Self {
+            stdin: child.stdin.take().ok_or(Error::Unknown)?,
+            stdout: child.stdout.take().ok_or(Error::Unknown)?,
+            child,
+            stderr,
+        })
+    }
+
+    /// Collect the exit status after the target stopped answering
+    fn exited(&mut self, timed_out: bool, stdout: Word) -> FResult<ChildRes> {
+        if timed_out {
+            info!("Killing {}", self.child.id());
+            unsafe { libc::kill(-(self.child.id() as i32), libc::SIGKILL) };
+        }
+        let status = self.child.wait()?;
+        Ok(ChildRes {
+            status: Some(status),
+            stdout,
+            stderr: self.stderr.read()?,
+            timed_out,
+        })
+    }
+
+    pub fn run(&mut self, data: &Word, timeout: Option<Duration>) -> FResult<ChildRes> {
+        self.stderr.clear()?;
+
+        let written = self
+            .stdin
+            .write_all(&(data.len() as u32).to_le_bytes())
+            .and_then(|_| self.stdin.write_all(data))
+            .and_then(|_| self.stdin.flush());
+        match written {
+            Err(err) if err.kind() == ErrorKind::BrokenPipe => return self.exited(false, vec![]),
+            x => x?,
+        }
+
+        let start = Instant::now();
+        let remaining =
+            |timeout: Option<Duration>| timeout.map(|x| x.saturating_sub(start.elapsed()));
+
+        let len = match read_u32(&mut self.stdout, remaining(timeout)) {
+            Ok(Some(len)) => len as usize,
+            Ok(None) => return self.exited(true, vec![]),
+            Err(Error::Io(err)) if err.kind() == ErrorKind::UnexpectedEof => {
+                return self.exited(false, vec![])
+            }
+            Err(err) => return Err(err),
+        };
+
+        let mut reply = vec![0; len];
+        let mut read = 0;
+        while read < len {
+            if !poll_readable(&self.stdout, remaining(timeout))? {
+                return self.exited(true, reply[..read].to_vec());
+            }
+            match self.stdout.read(&mut reply[read..])? {
+                0 => return self.exited(false, reply[..read].to_vec()),
+                n => read += n,
+            }
+        }
+
+        Ok(ChildRes {
+            status: None,
+            stdout: reply,
+            stderr: self.stderr.read()?,
+            timed_out: false,
+        })
+    }
+}
+
+impl Drop for PersistentServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        os::unix::process::{CommandExt, ExitStatusExt},
+        process::Command,
+        time::Duration,
+    };
+
+    use crate::core::error::Error;
+
+    use super::{ExecMode, Server};
+
+    /// Write a little endian u32 from a shell script
+    const LE: &str = r#"le() { printf "$(printf '\\%03o' $(($1 & 255)) $(($1 >> 8 & 255)) $(($1 >> 16 & 255)) $(($1 >> 24 & 255)))"; }"#;
+
+    /// A target script. bash is needed for the fork server's file descriptors above 9
+    fn script(body: &str) -> Command {
+        let mut command = Command::new("bash");
+        command
+            .arg("-c")
+            .arg(format!("{LE}\n{body}"))
+            .process_group(0);
+        command
+    }
+
+    #[test]
+    fn persistent() {
+        let target = r#"
+while len=$(dd bs=1 count=4 status=none | od -An -tu4 | tr -d ' ') && [ -n "$len" ]; do
+    data=$(dd bs=1 count=$len status=none)
+    [ "$data" = crash ] && kill -SEGV $$
+    [ "$data" = hang ] && sleep 5
+    echo "$data" >&2
+    reply="got:$data"
+    le ${#reply}
+    printf %s "$reply"
+done"#;
+        let timeout = Some(Duration::from_millis(500));
+        let mut server = Server::start(ExecMode::Persistent, script(target)).unwrap();
+
+        for input in ["abc", "de"] {
+            let res = server.run(&input.into(), timeout).unwrap();
+            assert_eq!(format!("got:{input}").into_bytes(), res.stdout);
+            assert_eq!(format!("{input}\n").into_bytes(), res.stderr);
+            assert!(!server.is_done(&res));
+        }
+
+        let res = server.run(&"crash".into(), timeout).unwrap();
+        assert_eq!(Some(libc::SIGSEGV), res.status.and_then(|x| x.signal()));
+        assert!(server.is_done(&res));
+
+        let mut server = Server::start(ExecMode::Persistent, script(target)).unwrap();
+        let res = server.run(&"hang".into(), timeout).unwrap();
+        assert!(res.timed_out);
+        assert!(server.is_done(&res));
+    }
+
+    #[test]
+    fn fork_server() {
+        // a fork server with the AFL++ 4.21 handshake that exits after two runs
+        let target = r#"
+le 1095126017 >&199
+[ "$(dd bs=1 count=4 status=none <&198 | od -An -tu4 | tr -d ' ')" = 3199841278 ] || exit 1
+le 0 >&199
+le 1095126017 >&199
+runs=0
+while [ $runs -lt 2 ] && [ "$(dd bs=1 count=4 status=none <&198 | wc -c)" = 4 ]; do
+    sh -c 'read line; echo "out:$line"; [ "$line" = fail ] && exit 3; exit 0' <&0 &
+    pid=$!
+    le $pid >&199
+    wait $pid
+    le $(($? << 8)) >&199
+    runs=$((runs + 1))
+done"#;
+        let mut server = Server::start(ExecMode::ForkServer, script(target)).unwrap();
+
+        let res = server.run(&"hello\n".into(), None).unwrap();
+        assert_eq!(b"out:hello\n".to_vec(), res.stdout);
+        assert_eq!(Some(0), res.status.and_then(|x| x.code()));
+        assert!(!server.is_done(&res));
+
+        let res = server.run(&"fail\n".into(), None).unwrap();
+        assert_eq!(Some(3), res.status.and_then(|x| x.code()));
+        assert!(!server.is_done(&res));
+
+        // the fork server is gone, the input did not run
+        assert!(matches!(
+            server.run(&"again\n".into(), None),
+            Err(Error::ForkServerExited)
+        ));
+
+        assert!(Server::start(ExecMode::ForkServer, script("exit 0")).is_err());
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    io::{ErrorKind, Read, Write},
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+
+use crate::core::{config::Config, error::FResult, transform::Word};
+
+use super::RunRes;
+
+/// Replies are cut off after this many bytes by default
+const DEFAULT_MAX_READ: usize = 1 << 20;
+
+/// The largest datagram that is received at once
+const MAX_DATAGRAM: usize = 65535;
+
+/// Connection errors that are reported as a result instead of failing the run
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum ConnErr {
+    Refused,
+    Reset,
+}
+
+impl ConnErr {
+    /// Classify an io error, None if it is not a connection error
+    pub fn from_io(err: &std::io::Error) -> Option<Self> {
+        match err.kind() {
+            ErrorKind::ConnectionRefused => Some(ConnErr::Refused),
+            ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted | ErrorKind::BrokenPipe => {
+                Some(ConnErr::Reset)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ConnErr::Refused => "connection refused",
+            ConnErr::Reset => "connection reset",
+        }
+    }
+}
+
+/// Options shared by all socket runners
+#[derive(Clone, Debug)]
+pub struct SocketOpts {
+    /// Connect timeout and the time a reply may be idle before it is considered complete
+    pub timeout: Duration,
+    /// Stop reading once the reply reaches this size
+    pub max_read: usize,
+    /// Close the write side of stream sockets after sending the input
+    pub shutdown: bool,
+}
+
+impl SocketOpts {
+    pub fn from_cfg(cfg: &Config) -> Self {
+        Self {
+            timeout: Duration::from_millis(cfg.socket_timeout.unwrap_or(1000) as u64),
+            max_read: cfg.socket_max_read.unwrap_or(DEFAULT_MAX_READ),
+            shutdown: cfg.socket_shutdown,
+        }
+    }
+}
+
+/// true if the error means that no data arrived within the read timeout
+pub fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+/// Turn a connection error into a result, all other errors fail the run
+pub fn conn_err_res<T>(res: std::io::Result<T>) -> FResult<Result<T, RunRes>> {
+    match res {
+        Ok(x) => Ok(Ok(x)),
+        Err(err) => match ConnErr::from_io(&err) {
+            Some(conn_err) => Ok(Err(RunRes {
+                conn_err: Some(conn_err),
+                ..Default::default()
+            })),
+            None if is_timeout(&err) => Ok(Err(RunRes {
+                timed_out: true,
+                ..Default::default()
+            })),
+            None => Err(err.into()),
+        },
+    }
+}
+
+/// Read a reply from a stream until EOF, the stream is idle for the read timeout
+/// or max_read bytes were read
+pub fn read_stream(stream: &mut impl Read, max_read: usize) -> FResult<(Word, Option<ConnErr>)> {
+    let mut reply = Vec::new();
+    let mut buffer = [0; 4096];
+    while reply.len() < max_read {
+        let len = buffer.len().min(max_read - reply.len());
+        match stream.read(&mut buffer[..len]) {
+            Ok(0) => break,
+            Ok(n) => reply.extend_from_slice(&buffer[..n]),
+            Err(err) if is_timeout(&err) => break,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => match ConnErr::from_io(&err) {
+                Some(conn_err) => return Ok((reply, Some(conn_err))),
+                None => return Err(err.into()),
+            },
+        }
+    }
+    Ok((reply, None))
+}
+
+/// Collect datagrams until the receive window ends or max_read bytes were received.
+/// recv receives a single datagram and may not block longer than the given timeout
+pub fn recv_datagrams(
+    opts: &SocketOpts,
+    mut recv: impl FnMut(&mut [u8], Duration) -> std::io::Result<usize>,
+) -> FResult<(Word, Option<ConnErr>)> {
+    let start = Instant::now();
+    let mut out = Vec::new();
+    let mut buffer = vec![0; MAX_DATAGRAM];
+    while out.len() < opts.max_read {
+        let remaining = opts.timeout.saturating_sub(start.elapsed());
+        if remaining.is_zero() {
+            break;
+        }
+        match recv(&mut buffer, remaining) {
+            Ok(n) => {
+                let n = n.min(opts.max_read - out.len());
+                out.extend_from_slice(&buffer[..n]);
+            }
+            Err(err) if is_timeout(&err) => break,
+            Err(err) if err.kind() == ErrorKind::Interrupted => {}
+            Err(err) => match ConnErr::from_io(&err) {
+                Some(conn_err) => return Ok((out, Some(conn_err))),
+                None => return Err(err.into()),
+            },
+        }
+    }
+    Ok((out, None))
+}
+
+/// The dry run output of socket runners, the address followed by the input
+pub fn dry_run_res(addr: &str, data: &Word) -> FResult<RunRes> {
+    let mut output = Vec::new();
+    output.write_all(addr.as_bytes())?;
+    output.write_all(b"\n\n")?;
+    output.write_all(data)?;
+    Ok(RunRes::new(None, output))
+}
@@ -0,0 +1,162 @@
+use std::{
+    io::Write,
+    net::{Shutdown, TcpStream, ToSocketAddrs},
+};
+
+use log::info;
+
+use crate::core::{
+    error::{Error, FResult},
+    transform::{Context, IterState, Word},
+};
+
+use super::{
+    replace_fuzz,
+    socket::{conn_err_res, dry_run_res, read_stream, SocketOpts},
+    CommandRunnerKind, RunRes,
+};
+
+/// A plain tcp connection to host:port
+#[derive(Clone)]
+pub struct Tcp {
+    pub addr: String,
+    pub opts: SocketOpts,
+    pub cmd_arg_target: String,
+}
+
+/// The command runner for tcp.
+/// It connects to the address, sends the input and returns the reply
+pub fn tcp_command_runner(
+    ctx: &Context,
+    runner: &CommandRunnerKind,
+    data: &Word,
+    state: &mut IterState,
+) -> FResult<RunRes> {
+    if let CommandRunnerKind::Tcp(tcp) = runner {
+        let addr = replace_fuzz(&tcp.addr, &tcp.cmd_arg_target, ctx, state)?;
+
+        if ctx.dry_run {
+            return dry_run_res(&addr, data);
+        }
+
+        info!("Connecting to {}", addr);
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or(Error::InsufficientRunnerConfiguration)?;
+
+        let mut stream = match conn_err_res(TcpStream::connect_timeout(&addr, tcp.opts.timeout))? {
+            Ok(stream) => stream,
+            Err(res) => return Ok(res),
+        };
+        stream.set_read_timeout(Some(tcp.opts.timeout))?;
+        stream.set_write_timeout(Some(tcp.opts.timeout))?;
+
+        if let Err(res) = conn_err_res(stream.write_all(data))? {
+            return Ok(res);
+        }
+        if tcp.opts.shutdown {
+            if let Err(res) = conn_err_res(stream.shutdown(Shutdown::Write))? {
+                return Ok(res);
+            }
+        }
+
+        let (out, conn_err) = read_stream(&mut stream, tcp.opts.max_read)?;
+        Ok(RunRes {
+            out,
+            conn_err,
+            ..Default::default()
+        })
+    } else {
+        Err(Error::UnsupportedCommandRunner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        time::Duration,
+    };
+
+    use crate::core::{
+        rand::Rand,
+        runner::{socket::ConnErr, socket::SocketOpts, CommandRunnerKind},
+        transform::{Context, IterState},
+    };
+
+    use super::{tcp_command_runner, Tcp};
+
+    fn tcp(addr: String) -> CommandRunnerKind {
+        CommandRunnerKind::Tcp(Tcp {
+            addr,
+            opts: SocketOpts {
+                timeout: Duration::from_millis(500),
+                max_read: 1024,
+                shutdown: true,
+            },
+            cmd_arg_target: "OXIFUZZ".into(),
+        })
+    }
+
+    #[test]
+    fn echo_and_refused() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = Vec::new();
+            stream.read_to_end(&mut buffer).unwrap();
+            stream.write_all(&buffer).unwrap();
+        });
+
+        let ctx = Context::default();
+        let mut rand = Rand::from_seed(1);
+        let res = tcp_command_runner(
+            &ctx,
+            &tcp(addr.to_string()),
+            &b"hello".to_vec(),
+            &mut IterState::new(0, &mut rand),
+        )
+        .unwrap();
+        server.join().unwrap();
+        assert_eq!(b"hello".to_vec(), res.out);
+        assert_eq!(None, res.conn_err);
+
+        // nothing listens on the port once the listener is dropped
+        let res = tcp_command_runner(
+            &ctx,
+            &tcp(addr.to_string()),
+            &b"hello".to_vec(),
+            &mut IterState::new(0, &mut rand),
+        )
+        .unwrap();
+        assert_eq!(Some(ConnErr::Refused), res.conn_err);
+    }
+
+    #[test]
+    fn write_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (done, wait) = std::sync::mpsc::channel::<()>();
+        // the server accepts but never reads
+        let server = std::thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            wait.recv().unwrap();
+        });
+
+        let ctx = Context::default();
+        let mut rand = Rand::from_seed(1);
+        let res = tcp_command_runner(
+            &ctx,
+            &tcp(addr.to_string()),
+            &vec![0; 8 * 1024 * 1024],
+            &mut IterState::new(0, &mut rand),
+        )
+        .unwrap();
+        done.send(()).unwrap();
+        server.join().unwrap();
+        assert!(res.timed_out);
+    }
+}
@@ -0,0 +1,131 @@
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use log::info;
+
+use crate::core::{
+    error::{Error, FResult},
+    transform::{Context, IterState, Word},
+};
+
+use super::{
+    replace_fuzz,
+    socket::{conn_err_res, dry_run_res, recv_datagrams, SocketOpts},
+    CommandRunnerKind, RunRes,
+};
+
+/// A udp socket that sends the input as one datagram to host:port
+#[derive(Clone)]
+pub struct Udp {
+    pub addr: String,
+    pub opts: SocketOpts,
+    pub cmd_arg_target: String,
+}
+
+/// The command runner for udp.
+/// It sends the input as a single datagram and collects all replies
+/// that arrive within the receive window
+pub fn udp_command_runner(
+    ctx: &Context,
+    runner: &CommandRunnerKind,
+    data: &Word,
+    state: &mut IterState,
+) -> FResult<RunRes> {
+    if let CommandRunnerKind::Udp(udp) = runner {
+        let addr = replace_fuzz(&udp.addr, &udp.cmd_arg_target, ctx, state)?;
+
+        if ctx.dry_run {
+            return dry_run_res(&addr, data);
+        }
+
+        info!("Sending to {}", addr);
+        let addr = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or(Error::InsufficientRunnerConfiguration)?;
+        let local: SocketAddr = if addr.is_ipv4() {
+            "0.0.0.0:0".parse()
+        } else {
+            "[::]:0".parse()
+        }
+        .map_err(|_| Error::Unknown)?;
+
+        let socket = UdpSocket::bind(local)?;
+        // a connected socket only receives replies from the target
+        // and reports icmp port unreachable as connection refused
+        socket.connect(addr)?;
+        if let Err(res) = conn_err_res(socket.send(data))? {
+            return Ok(res);
+        }
+
+        let (out, conn_err) = recv_datagrams(&udp.opts, |buffer, timeout| {
+            socket.set_read_timeout(Some(timeout))?;
+            socket.recv(buffer)
+        })?;
+
+        Ok(RunRes {
+            out,
+            conn_err,
+            ..Default::default()
+        })
+    } else {
+        Err(Error::UnsupportedCommandRunner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::UdpSocket, time::Duration};
+
+    use crate::core::{
+        rand::Rand,
+        runner::{socket::ConnErr, socket::SocketOpts, CommandRunnerKind},
+        transform::{Context, IterState},
+    };
+
+    use super::{udp_command_runner, Udp};
+
+    fn udp(addr: String) -> CommandRunnerKind {
+        CommandRunnerKind::Udp(Udp {
+            addr,
+            opts: SocketOpts {
+                timeout: Duration::from_millis(300),
+                max_read: 1024,
+                shutdown: false,
+            },
+            cmd_arg_target: "OXIFUZZ".into(),
+        })
+    }
+
+    #[test]
+    fn datagrams_and_refused() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = server.local_addr().unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut buffer = [0; 64];
+            let (n, from) = server.recv_from(&mut buffer).unwrap();
+            server.send_to(&buffer[..n], from).unwrap();
+            server.send_to(b"!", from).unwrap();
+        });
+
+        let ctx = Context::default();
+        let mut rand = Rand::from_seed(1);
+        let res = udp_command_runner(
+            &ctx,
+            &udp(addr.to_string()),
+            &b"ping".to_vec(),
+            &mut IterState::new(0, &mut rand),
+        )
+        .unwrap();
+        handle.join().unwrap();
+        assert_eq!(b"ping!".to_vec(), res.out);
+
+        let res = udp_command_runner(
+            &ctx,
+            &udp(addr.to_string()),
+            &b"ping".to_vec(),
+            &mut IterState::new(0, &mut rand),
+        )
+        .unwrap();
+        assert_eq!(Some(ConnErr::Refused), res.conn_err);
+    }
+}
@@ -0,0 +1,289 @@
+use std::{
+    io::{ErrorKind, Write},
+    os::{
+        fd::{FromRawFd, OwnedFd},
+        unix::{
+            ffi::OsStrExt,
+            net::{UnixDatagram, UnixStream},
+        },
+    },
+    path::Path,
+};
+
+#[cfg(feature = "cli")]
+use clap::ValueEnum;
+use log::info;
+
+use crate::core::{
+    error::{Error, FResult},
+    transform::{Context, IterState, Word},
+};
+
+use super::{
+    replace_fuzz,
+    socket::{conn_err_res, dry_run_res, read_stream, recv_datagrams, SocketOpts},
+    temp_path, CommandRunnerKind, RunRes,
+};
+
+/// Type of a unix domain socket
+#[cfg_attr(feature = "cli", derive(ValueEnum))]
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub enum UnixKind {
+    #[default]
+    Stream,
+    Datagram,
+    /// Connection based socket that keeps message boundaries
+    Seqpacket,
+}
+
+/// A unix domain socket at a path
+#[derive(Clone)]
+pub struct Unix {
+    pub path: String,
+    pub kind: UnixKind,
+    pub opts: SocketOpts,
+    pub cmd_arg_target: String,
+}
+
+/// Build the address of a unix socket path
+pub fn sockaddr(path: &Path) -> FResult<(libc::sockaddr_un, libc::socklen_t)> {
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    let bytes = path.as_os_str().as_bytes();
+    // the path has to be nul terminated
+    if bytes.len() >= addr.sun_path.len() {
+        return Err(Error::InsufficientRunnerConfiguration);
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+        *dst = *src as libc::c_char;
+    }
+    let len = std::mem::size_of::<libc::sa_family_t>() + bytes.len() + 1;
+    Ok((addr, len as libc::socklen_t))
+}
+
+/// A socket path that does not exist means that the target is not listening
+fn missing_as_refused<T>(res: std::io::Result<T>) -> std::io::Result<T> {
+    res.map_err(|err| {
+        if err.kind() == ErrorKind::NotFound {
+            ErrorKind::ConnectionRefused.into()
+        } else {
+            err
+        }
+    })
+}
+
+/// Connect a seqpacket socket.
+/// The socket is returned as a stream since reads and writes behave the same,
+/// except that every read returns at most one packet
+fn connect_seqpacket(path: &Path) -> FResult<std::io::Result<UnixStream>> {
+    let (addr, len) = sockaddr(path)?;
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+    let res = unsafe {
+        libc::connect(
+            std::os::fd::AsRawFd::as_raw_fd(&fd),
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len,
+        )
+    };
+    if res < 0 {
+        return Ok(Err(std::io::Error::last_os_error()));
+    }
+    Ok(Ok(UnixStream::from(fd)))
+}
+
+/// Send the input on a connected stream or seqpacket socket and read the reply
+fn run_stream(
+    stream: std::io::Result<UnixStream>,
+    data: &Word,
+    opts: &SocketOpts,
+) -> FResult<RunRes> {
+    let mut stream = match conn_err_res(stream)? {
+        Ok(stream) => stream,
+        Err(res) => return Ok(res),
+    };
+    stream.set_read_timeout(Some(opts.timeout))?;
+    stream.set_write_timeout(Some(opts.timeout))?;
+
+    if let Err(res) = conn_err_res(stream.write_all(data))? {
+        return Ok(res);
+    }
+    if opts.shutdown {
+        if let Err(res) = conn_err_res(stream.shutdown(std::net::Shutdown::Write))? {
+            return Ok(res);
+        }
+    }
+
+    let (out, conn_err) = read_stream(&mut stream, opts.max_read)?;
+    Ok(RunRes {
+        out,
+        conn_err,
+        ..Default::default()
+    })
+}
+
+/// Send the input as one datagram and collect the replies within the receive window.
+/// The socket is bound to a temporary path so that the target can reply
+fn run_datagram(path: &Path, data: &Word, opts: &SocketOpts) -> FResult<RunRes> {
+    let local = temp_path("unix");
+    let socket = UnixDatagram::bind(&local)?;
+    let res = (|| {
+        if let Err(res) = conn_err_res(missing_as_refused(socket.connect(path)))? {
+            return Ok(res);
+        }
+        if let Err(res) = conn_err_res(socket.send(data))? {
+            return Ok(res);
+        }
+
+        let (out, conn_err) = recv_datagrams(opts, |buffer, timeout| {
+            socket.set_read_timeout(Some(timeout))?;
+            socket.recv(buffer)
+        })?;
+        Ok(RunRes {
+            out,
+            conn_err,
+            ..Default::default()
+        })
+    })();
+    let _ = std::fs::remove_file(&local);
+    res
+}
+
+/// The command runner for unix domain sockets.
+/// It connects to the socket path, sends the input and returns the reply
+pub fn unix_command_runner(
+    ctx: &Context,
+    runner: &CommandRunnerKind,
+    data: &Word,
+    state: &mut IterState,
+) -> FResult<RunRes> {
+    if let CommandRunnerKind::Unix(unix) = runner {
+        let path = replace_fuzz(&unix.path, &unix.cmd_arg_target, ctx, state)?;
+
+        if ctx.dry_run {
+            return dry_run_res(&path, data);
+        }
+
+        info!("Connecting to {} ({:?})", path, unix.kind);
+        let path = Path::new(&path);
+        match unix.kind {
+            UnixKind::Stream => run_stream(
+                missing_as_refused(UnixStream::connect(path)),
+                data,
+                &unix.opts,
+            ),
+            UnixKind::Seqpacket => run_stream(
+                missing_as_refused(connect_seqpacket(path)?),
+                data,
+                &unix.opts,
+            ),
+            UnixKind::Datagram => run_datagram(path, data, &unix.opts),
+        }
+    } else {
+        Err(Error::UnsupportedCommandRunner)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        io::{Read, Write},
+        os::{
+            fd::{AsRawFd, FromRawFd, OwnedFd},
+            unix::net::{UnixDatagram, UnixListener},
+        },
+        path::Path,
+        time::Duration,
+    };
+
+    use crate::core::{
+        rand::Rand,
+        runner::{socket::ConnErr, socket::SocketOpts, temp_path, CommandRunnerKind, RunRes},
+        transform::{Context, IterState},
+    };
+
+    use super::{sockaddr, unix_command_runner, Unix, UnixKind};
+
+    fn run(path: &Path, kind: UnixKind) -> RunRes {
+        let runner = CommandRunnerKind::Unix(Unix {
+            path: path.to_string_lossy().to_string(),
+            kind,
+            opts: SocketOpts {
+                timeout: Duration::from_millis(300),
+                max_read: 1024,
+                shutdown: false,
+            },
+            cmd_arg_target: "OXIFUZZ".into(),
+        });
+        let mut rand = Rand::from_seed(1);
+        unix_command_runner(
+            &Context::default(),
+            &runner,
+            &b"ping".to_vec(),
+            &mut IterState::new(0, &mut rand),
+        )
+        .unwrap()
+    }
+
+    /// A stand-in server that echoes the first message of one client
+    fn echo_stream(listener: UnixListener) -> std::thread::JoinHandle<()> {
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0; 64];
+            let n = stream.read(&mut buffer).unwrap();
+            stream.write_all(&buffer[..n]).unwrap();
+        })
+    }
+
+    fn seqpacket_listener(path: &Path) -> UnixListener {
+        let (addr, len) = sockaddr(path).unwrap();
+        unsafe {
+            let fd = OwnedFd::from_raw_fd(libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET, 0));
+            assert_eq!(
+                0,
+                libc::bind(
+                    fd.as_raw_fd(),
+                    &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+                    len
+                )
+            );
+            assert_eq!(0, libc::listen(fd.as_raw_fd(), 1));
+            UnixListener::from(fd)
+        }
+    }
+
+    #[test]
+    fn stream_datagram_seqpacket() {
+        let path = temp_path("stream");
+        let server = echo_stream(UnixListener::bind(&path).unwrap());
+        assert_eq!(b"ping".to_vec(), run(&path, UnixKind::Stream).out);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Some(ConnErr::Refused),
+            run(&path, UnixKind::Stream).conn_err
+        );
+
+        let path = temp_path("seqpacket");
+        let server = echo_stream(seqpacket_listener(&path));
+        assert_eq!(b"ping".to_vec(), run(&path, UnixKind::Seqpacket).out);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let path = temp_path("datagram");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        let server = std::thread::spawn(move || {
+            let mut buffer = [0; 64];
+            let (n, from) = socket.recv_from(&mut buffer).unwrap();
+            let from = from.as_pathname().unwrap();
+            socket.send_to(&buffer[..n], from).unwrap();
+            socket.send_to(b"!", from).unwrap();
+        });
+        assert_eq!(b"ping!".to_vec(), run(&path, UnixKind::Datagram).out);
+        server.join().unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+}
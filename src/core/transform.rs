@@ -1,11 +1,24 @@
 use super::{
-    config::Config,
+    baseline::{ResponseBaseline, TimingBaseline},
+    config::{Config, Strategy, Stream},
+    corpus::{CorpusDir, CorpusMeta, CorpusReason, META_EXTENSION},
+    coverage::Coverage,
     error::{Error, FResult},
-    rand::Rand,
-    runner::CommandRunner,
+    expr::{Cmp, Metric},
+    json::JsonExpect,
+    mutate::Mutator,
+    rand::{iteration_seed, Rand},
+    reflect::{is_reflected, Encoding},
+    runner::{crash::Crash, server::Server, socket::ConnErr, CommandRunner, RunRes},
 };
 use console::style;
 use log::debug;
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
 
 pub type Word = Vec<u8>;
 
@@ -15,7 +28,7 @@ pub const DEFAULT_USER_AGENT: &str = "oxifuzz/0.1";
 /// A target represents
 /// a string of bytes that will be replaced with random data
 /// from the word list
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Target {
     Word(Word),
 }
@@ -41,6 +54,53 @@ impl Target {
     }
 }
 
+/// A target and the word list it is replaced with
+#[derive(Clone, Default)]
+pub struct TargetWords {
+    pub target: Target,
+    pub words: Vec<Word>,
+}
+
+impl TargetWords {
+    pub fn new(target: Target, words: Vec<Word>) -> Self {
+        Self { target, words }
+    }
+}
+
+/// A word that replaced an occurrence of a target
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct Substitution {
+    /// The placeholder of the target
+    pub target: Word,
+    /// Index of the word in the target's word list
+    pub index: usize,
+    pub word: Word,
+}
+
+/// State of a single iteration that is passed to every word selection
+pub struct IterState<'a> {
+    pub rand: &'a mut Rand,
+    pub iteration: u32,
+    /// The target of every occurrence that was replaced so far
+    pub slots: Vec<usize>,
+    /// The words that were substituted so far
+    pub substitutions: Vec<Substitution>,
+    /// Word indexes of a replayed run that are used instead of selecting words
+    pub replayed: Option<VecDeque<usize>>,
+}
+
+impl<'a> IterState<'a> {
+    pub fn new(iteration: u32, rand: &'a mut Rand) -> Self {
+        Self {
+            rand,
+            iteration,
+            slots: vec![],
+            substitutions: vec![],
+            replayed: None,
+        }
+    }
+}
+
 /// Possible exit values for runners
 #[derive(Copy, Clone, Eq, PartialEq, Default, Debug)]
 pub enum ExitCodes {
@@ -48,6 +108,8 @@ pub enum ExitCodes {
     Success,
     Failure,
     RunnerFailed,
+    Crash,
+    Hang,
     Unknown,
 }
 
@@ -64,6 +126,8 @@ impl From<ExitCodes> for i32 {
             ExitCodes::Success => 0,
             ExitCodes::Failure => 1,
             ExitCodes::RunnerFailed => 2,
+            ExitCodes::Crash => 3,
+            ExitCodes::Hang => 4,
             ExitCodes::Unknown => -1,
         }
     }
@@ -75,6 +139,29 @@ pub struct ExecRes {
     pub exit_code: ExitCodes,
     pub out: Word,
     pub fmt: OutputFmt,
+    /// The exit code reported by the runner
+    pub status: Option<i32>,
+    /// The data that was passed to the runner
+    pub input: Word,
+    /// The run hit edges that no previous run hit
+    pub new_coverage: bool,
+    pub crash: Option<Crash>,
+    /// The runner did not finish before its timeout
+    pub timed_out: bool,
+    /// Diagnostic output of the runner
+    pub err: Word,
+    pub conn_err: Option<ConnErr>,
+    /// The input file that was kept by the file runner
+    pub file: Option<PathBuf>,
+    /// Wall-clock time of the run
+    pub duration: Duration,
+    /// The words that were substituted into the input and the runner's templates
+    pub substitutions: Vec<Substitution>,
+    /// The word indexes that were substituted into the runner's templates
+    pub template_words: Vec<usize>,
+    pub iteration: u32,
+    /// The seed of the iteration's rand. Runs that read a random file have none
+    pub seed: Option<u64>,
 }
 
 /// Possible formatting options
@@ -84,6 +171,9 @@ pub enum OutputFmt {
     None,
     Expected,
     NotExpected,
+    Crash,
+    Hang,
+    ConnErr,
 }
 
 /// Iterator around context that can apply transforms to input data 0 to n times
@@ -91,21 +181,321 @@ pub enum OutputFmt {
 pub struct ContextIter {
     count: u32,
     n_run: u32,
+    step: u32,
     pub ctx: Context,
     rand: Rand,
     input: Vec<u8>,
+    mutator: Mutator,
+    /// Inputs loaded from corpus directories
+    seeds: Vec<Word>,
+    corpus: Option<CorpusDir>,
+    seed: Option<u64>,
 }
 
 impl ContextIter {
     /// Create from config
     pub fn from_cfg(cfg: &Config) -> FResult<Self> {
-        Ok(ContextIter {
-            n_run: cfg.n_run,
+        let mut ctx = Context::from_cfg(cfg)?;
+        let input = Context::read_all(&mut cfg.input()?)?;
+
+        let n_run = if ctx.strategy == Strategy::Random {
+            cfg.n_run
+        } else {
+            ctx.slots = ctx.enumerate_slots(&input)?;
+            let n_run = ctx.enumeration_len();
+            debug!("Enumerating {} slots in {} runs", ctx.slots.len(), n_run);
+            n_run
+        };
+
+        let iter = ContextIter {
+            n_run,
             count: 0,
+            step: 1,
             rand: cfg.rand(),
-            ctx: Context::from_cfg(cfg)?,
-            input: Context::read_all(&mut cfg.input()?)?,
-        })
+            ctx,
+            input,
+            mutator: Mutator::from_cfg(cfg)?,
+            seeds: cfg
+                .load_corpus
+                .iter()
+                .map(|x| CorpusDir::load(x))
+                .try_collect::<Vec<_>>()?
+                .concat(),
+            corpus: cfg
+                .corpus_dir
+                .as_ref()
+                .map(|x| CorpusDir::new(x))
+                .transpose()?,
+            seed: cfg.seed,
+        };
+        // a replayed run is judged on its own
+        let replay = cfg.replay.is_some() || cfg.replay_file.is_some();
+        if !replay && iter.ctx.expect.iter().any(|x| x.needs_calibration()) {
+            iter.calibrate(&cfg.calibration_words())?;
+        }
+        Ok(iter)
+    }
+
+    /// Run the input once for every benign word, or more often if an expectation needs more runs,
+    /// and let all expectations learn from the results.
+    /// Every target is replaced by the same word and mutations are not applied
+    pub fn calibrate(&self, words: &[Word]) -> FResult<()> {
+        let Some(runner) = &self.ctx.runner else {
+            return Ok(());
+        };
+        if self.ctx.dry_run {
+            return Ok(());
+        }
+
+        let mut samples = Vec::new();
+        // calibration does not use the run's rand so that seeded runs stay the same
+        let mut rand = Rand::from_seed(0);
+        let n_run = self
+            .ctx
+            .expect
+            .iter()
+            .map(|x| x.calibration_runs())
+            .fold(words.len(), usize::max);
+        for word in words.iter().cycle().take(n_run) {
+            let mut ctx = self.ctx.clone();
+            ctx.strategy = Strategy::Random;
+            ctx.slots = vec![];
+            for target in ctx.targets.iter_mut() {
+                target.words = vec![word.to_owned()];
+            }
+
+            let mut state = IterState::new(0, &mut rand);
+            let data = ctx.substitute(&self.input, &ctx.targets[0].target, &mut state)?;
+            debug!("Calibrating with {:?}", data);
+            if let Some(coverage) = &ctx.coverage {
+                coverage.clear();
+            }
+            let res = runner.run(&ctx, &data, &mut state)?;
+            runner.discard(&res)?;
+            samples.push(res);
+        }
+        // the edges of calibration runs must not count for the first iteration
+        if let Some(coverage) = &self.ctx.coverage {
+            coverage.clear();
+        }
+        // neither must the state of a long lived target
+        self.ctx.server.lock().map_err(|_| Error::Unknown)?.take();
+
+        for expect in self.ctx.expect.iter() {
+            expect.calibrate(&samples);
+        }
+        Ok(())
+    }
+
+    /// Create a copy of this iterator for one of n workers.
+    /// Each worker runs every n-th iteration starting at its own index
+    pub fn for_worker(&self, worker: u32, n_worker: u32, rand: Rand) -> FResult<Self> {
+        let mut res = Self {
+            count: self.count + worker,
+            step: self.step * n_worker.max(1),
+            rand,
+            ..self.clone()
+        };
+        res.ctx = self.ctx.for_worker()?;
+        Ok(res)
+    }
+
+    /// Select the input the next run is based on.
+    /// This is either the original input, a seed input
+    /// or any input that found new coverage
+    fn select_input(ctx: &Context, input: &[u8], seeds: &[Word], rand: &mut Rand) -> FResult<Word> {
+        let coverage_len = if let Some(coverage) = &ctx.coverage {
+            coverage.corpus_len()?
+        } else {
+            0
+        };
+        let len = seeds.len() + coverage_len;
+        if len > 0 {
+            let index = rand.next_range(0, len as u64 + 1)? as usize;
+            if let Some(seed) = seeds.get(index) {
+                return Ok(seed.to_owned());
+            } else if let Some(coverage) = &ctx.coverage {
+                if let Some(input) = coverage.get(index - seeds.len())? {
+                    return Ok(input);
+                }
+            }
+        }
+        Ok(input.to_owned())
+    }
+
+    /// Save the input of a result to the corpus directory if it is interesting
+    fn save(&self, iteration: u32, res: &ExecRes) -> FResult<()> {
+        let Some(corpus) = &self.corpus else {
+            return Ok(());
+        };
+
+        let mut reasons = vec![];
+        if res.fmt == OutputFmt::Expected {
+            reasons.push(CorpusReason::Expected);
+        }
+        if res.new_coverage {
+            reasons.push(CorpusReason::Coverage);
+        }
+        if res.crash.is_some() {
+            reasons.push(CorpusReason::Crash);
+        }
+        if res.timed_out {
+            reasons.push(CorpusReason::Hang);
+        }
+        if res.conn_err.is_some() {
+            reasons.push(CorpusReason::Connection);
+        }
+        if reasons.is_empty() {
+            return Ok(());
+        }
+
+        corpus.save(
+            &res.input,
+            &CorpusMeta {
+                reasons,
+                seed: self.seed,
+                iteration,
+                iteration_seed: res.seed,
+                template_words: res.template_words.to_owned(),
+                runner: self
+                    .ctx
+                    .runner
+                    .as_ref()
+                    .map(|x| x.kind.name())
+                    .unwrap_or("none")
+                    .into(),
+                exit_code: res.status,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// The seed of an iteration's rand. Seeded runs derive it from the run's seed,
+    /// otherwise it is taken from the run's rand.
+    /// Random files have no seed, their iterations read directly from the file
+    fn iteration_seed(&mut self, iteration: u32) -> FResult<Option<u64>> {
+        match (self.seed, &self.rand) {
+            (Some(seed), _) => Ok(Some(iteration_seed(seed, iteration))),
+            (None, Rand::File(_)) => Ok(None),
+            (None, Rand::Random(_)) => self.rand.next_gen().map(Some),
+        }
+    }
+
+    /// Run a single iteration with its rand
+    fn iterate(&self, ctx: &Context, iteration: u32, rand: &mut Rand) -> FResult<ExecRes> {
+        let mut state = IterState::new(iteration, rand);
+
+        let base = Self::select_input(ctx, &self.input, &self.seeds, state.rand)?;
+        let input = {
+            // the corpus is only locked while the input is mutated
+            let coverage = ctx.coverage.as_ref().map(|x| x.corpus()).transpose()?;
+            let coverage = coverage
+                .as_deref()
+                .map(|x| x.as_slice())
+                .unwrap_or_default();
+            self.mutator
+                .mutate(&base, &[&self.seeds, coverage], state.rand)?
+        };
+
+        if let Some(coverage) = &ctx.coverage {
+            coverage.clear();
+        }
+        ctx.apply(&input, &mut state)
+    }
+
+    /// Run a single iteration and add the input to the coverage corpus if it found new edges
+    fn run(&mut self) -> FResult<ExecRes> {
+        let iteration = self.count;
+        let seed = self.iteration_seed(iteration)?;
+        self.count = self.count.saturating_add(self.step.max(1));
+
+        let mut res = match seed {
+            Some(seed) => self.iterate(&self.ctx, iteration, &mut Rand::from_seed(seed))?,
+            None => {
+                let mut rand = std::mem::replace(&mut self.rand, Rand::from_seed(0));
+                let res = self.iterate(&self.ctx, iteration, &mut rand);
+                self.rand = rand;
+                res?
+            }
+        };
+        res.seed = seed;
+        if let Some(coverage) = &self.ctx.coverage {
+            if coverage.has_new_bits()? {
+                res.new_coverage = true;
+                // like the corpus directory the coverage corpus keeps the input that was sent
+                coverage.add(res.input.to_owned())?;
+            }
+        }
+        self.save(iteration, &res)?;
+        Ok(res)
+    }
+
+    /// A copy of the context that only renders requests
+    fn dry_run_ctx(&self) -> Context {
+        Context {
+            dry_run: true,
+            // rendering must not teach the expectations anything
+            expect: vec![],
+            ..self.ctx.clone()
+        }
+    }
+
+    /// Run a single iteration again.
+    /// Returns the request as it is rendered by the runner's dry run and the result.
+    /// Runs with coverage are refused, their inputs depend on the coverage corpus of the original run
+    pub fn replay(&self, iteration: u32, seed: Option<u64>) -> FResult<(Word, ExecRes)> {
+        if self.ctx.coverage.is_some() {
+            return Err(Error::CoverageReplay);
+        }
+        let seed = match (seed, self.seed) {
+            (Some(seed), _) => seed,
+            (None, Some(seed)) => iteration_seed(seed, iteration),
+            (None, None) => return Err(Error::MissingReplaySeed),
+        };
+        let request = self.iterate(&self.dry_run_ctx(), iteration, &mut Rand::from_seed(seed))?;
+        let mut res = self.iterate(&self.ctx, iteration, &mut Rand::from_seed(seed))?;
+        res.seed = Some(seed);
+        Ok((request.out, res))
+    }
+
+    /// Run an input of a corpus directory again.
+    /// The input is sent as is, targets of the runner's templates are replaced
+    /// with the words that are recorded in the metadata.
+    /// Inputs that were only saved for their coverage are refused
+    pub fn replay_file(&self, path: &Path) -> FResult<(Word, ExecRes)> {
+        let data = std::fs::read(path)?;
+        let meta_path = path.with_extension(META_EXTENSION);
+        if !meta_path.exists() {
+            return Err(Error::UnreplayableFile);
+        }
+        let meta = CorpusMeta::parse(&std::fs::read_to_string(meta_path)?)?;
+        if !meta.reasons.iter().any(|x| *x != CorpusReason::Coverage) {
+            return Err(Error::UnreplayableFile);
+        }
+
+        let exec = |ctx: &Context| -> FResult<ExecRes> {
+            let mut rand = Rand::from_seed(meta.iteration_seed.unwrap_or_default());
+            let mut state = IterState::new(meta.iteration, &mut rand);
+            state.replayed = Some(meta.template_words.iter().copied().collect());
+            let mut res = ctx.maybe_exec(&data, &mut state)?;
+            res.input = data.to_owned();
+            res.template_words = meta.template_words.to_owned();
+            res.substitutions = state.substitutions;
+            res.iteration = meta.iteration;
+            res.seed = meta.iteration_seed;
+            Ok(res)
+        };
+        Ok((exec(&self.dry_run_ctx())?.out, exec(&self.ctx)?))
+    }
+
+    /// The index of the next iteration
+    pub fn iteration(&self) -> u32 {
+        self.count
+    }
+
+    /// true if all iterations of this iterator ran
+    pub fn is_done(&self) -> bool {
+        self.count >= self.n_run
     }
 }
 
@@ -113,9 +503,8 @@ impl std::iter::Iterator for ContextIter {
     type Item = FResult<ExecRes>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.count < self.n_run {
-            self.count += 1;
-            Some(self.ctx.apply(&self.input, &mut self.rand))
+        if !self.is_done() {
+            Some(self.run())
         } else {
             None
         }
@@ -125,14 +514,24 @@ impl std::iter::Iterator for ContextIter {
 /// The runtime configuration for a transform
 #[derive(Clone, Default)]
 pub struct Context {
-    words: Vec<Word>,
-    target: Target,
+    /// All targets, the first target is always the default target
+    targets: Vec<TargetWords>,
+
+    strategy: Strategy,
+    /// The target of every occurrence in the order they are replaced.
+    /// Only required for strategies that enumerate the word lists
+    slots: Vec<usize>,
 
     pub expect: Vec<Expect>,
 
     pub runner: Option<CommandRunner>,
 
     pub dry_run: bool,
+
+    pub coverage: Option<Coverage>,
+
+    /// Long lived target of the shell runner
+    pub server: Arc<Mutex<Option<Server>>>,
 }
 
 impl Context {
@@ -144,21 +543,109 @@ impl Context {
     /// create from config with a custom runner
     pub fn from_cfg_with_runner(cfg: &Config, runner: Option<CommandRunner>) -> FResult<Self> {
         Ok(Self {
-            words: cfg.words()?,
-            target: Target::Word(cfg.target.to_owned().into_bytes()),
+            targets: cfg.targets()?,
+            strategy: cfg.strategy,
+            slots: vec![],
 
             expect: Expect::from_cfg(cfg)?,
 
             runner,
             dry_run: cfg.dry_run,
+            coverage: if cfg.coverage {
+                Some(Coverage::new()?)
+            } else {
+                None
+            },
+            server: Default::default(),
         })
     }
 
-    /// select a word to replace the input data with
-    pub fn select_word(&self, rand: &mut Rand) -> FResult<&Word> {
-        let index = rand.next_range(0, self.words.len() as u64)?;
+    /// Create a copy for a worker thread.
+    /// Resources that can not be shared between workers are re-created
+    pub fn for_worker(&self) -> FResult<Self> {
+        Ok(Self {
+            coverage: self.coverage.as_ref().map(|x| x.for_worker()).transpose()?,
+            server: Default::default(),
+            ..self.clone()
+        })
+    }
+
+    /// select a word from a target's word list to replace the input data with
+    pub fn select_word(&self, target: usize, state: &mut IterState) -> FResult<&Word> {
+        let words = &self.targets[target].words;
+        if words.is_empty() {
+            return Err(Error::EmptyWordList);
+        }
+        let slot = state.slots.len();
+        state.slots.push(target);
+
+        let len = words.len() as u64;
+        let index = match (&mut state.replayed, self.strategy) {
+            (Some(replayed), _) => replayed.pop_front().ok_or(Error::UnreplayableFile)? as u64,
+            (None, Strategy::Random) => state.rand.next_range(0, len)?,
+            (None, Strategy::Sequential | Strategy::Pitchfork) => state.iteration as u64 % len,
+            (None, Strategy::Cartesian) => {
+                // every slot is a digit of the iteration with the word list length as its base
+                let radix = self
+                    .slots
+                    .iter()
+                    .take(slot)
+                    .fold(1u64, |acc, x| acc.saturating_mul(self.slot_len(*x)));
+                (state.iteration as u64 / radix) % len
+            }
+        };
+
+        let index = (index as usize).min(words.len() - 1);
+        let Target::Word(placeholder) = &self.targets[target].target;
+        state.substitutions.push(Substitution {
+            target: placeholder.to_owned(),
+            index,
+            word: words[index].to_owned(),
+        });
+        Ok(&words[index])
+    }
+
+    fn slot_len(&self, target: usize) -> u64 {
+        self.targets[target].words.len().max(1) as u64
+    }
+
+    /// Find all occurrences of targets in the input and the runner's templates
+    pub fn enumerate_slots(&self, input: &[u8]) -> FResult<Vec<usize>> {
+        let mut rand = Rand::from_seed(0);
+        let mut state = IterState::new(0, &mut rand);
+        self.substitute(input, &self.targets[0].target, &mut state)?;
+        if let Some(runner) = &self.runner {
+            for (template, target) in runner.kind.templates() {
+                let primary = Target::Word(target.as_bytes().to_vec());
+                self.substitute(template, &primary, &mut state)?;
+            }
+        }
+        Ok(state.slots)
+    }
+
+    /// The amount of runs required to enumerate all slots using the current strategy
+    pub fn enumeration_len(&self) -> u32 {
+        let lens = self.slots.iter().map(|x| self.slot_len(*x));
+        let len = match self.strategy {
+            Strategy::Random => 1,
+            Strategy::Sequential => lens.max().unwrap_or(1),
+            Strategy::Pitchfork => lens.min().unwrap_or(1),
+            Strategy::Cartesian => lens.fold(1u64, |acc, x| acc.saturating_mul(x)),
+        };
+        len.min(u32::MAX as u64) as u32
+    }
 
-        Ok(&self.words[(index as usize).min(self.words.len() - 1)])
+    /// find the target that applies to the input data.
+    /// The default target may be overwritten by primary.
+    /// If multiple targets apply the longest one wins
+    fn find_target(&self, input: &[u8], primary: &Target) -> Option<(usize, usize)> {
+        self.targets
+            .iter()
+            .enumerate()
+            .map(|(i, x)| if i == 0 { (i, primary) } else { (i, &x.target) })
+            .filter(|(_, target)| target.len() > 0 && target.should_replace(input))
+            .map(|(i, target)| (i, target.len()))
+            .max_by_key(|(_, len)| *len)
     }
 
     /// Read all bytes from an input stream
@@ -169,9 +656,9 @@ impl Context {
     }
 
     /// Compares the expected value with the actual result
-    pub fn compare_expected(&self, data: &Word, exit_code: Option<i32>) -> bool {
+    pub fn compare_expected(&self, res: &RunRes) -> bool {
         for e in self.expect.iter() {
-            if e.expect(data, exit_code) {
+            if e.expect(res) {
                 return true;
             }
         }
@@ -179,15 +666,44 @@ impl Context {
     }
 
     /// helper for formatted output to any stream
-    pub fn output(
+    pub fn output(cfg: &Config, output: &mut dyn std::io::Write, res: &ExecRes) -> FResult<()> {
+        // plain output is often used as input of other tools and stays unchanged
+        Self::write(cfg, output, res, res.fmt != OutputFmt::None)
+    }
+
+    /// Output of a replayed run. The rendered request is followed by the result with all details
+    pub fn output_replay(
+        cfg: &Config,
+        output: &mut dyn std::io::Write,
+        request: &Word,
+        res: &ExecRes,
+    ) -> FResult<()> {
+        if console::colors_enabled() {
+            console::set_colors_enabled(!cfg.no_color);
+        }
+        writeln!(output, "{}", style("request:").bold())?;
+        writeln!(output, "{}", String::from_utf8_lossy(request))?;
+        writeln!(output, "{}", style("response:").bold())?;
+        Self::write(cfg, output, res, true)?;
+        // stderr is already part of the result if it is shown
+        if !cfg.show_stderr && !res.err.is_empty() {
+            writeln!(output, "{}", style("stderr:").bold())?;
+            writeln!(output, "{}", String::from_utf8_lossy(&res.err).trim_end())?;
+        }
+        Ok(())
+    }
+
+    /// Formatted output of a result. Verbose output includes the details of the run
+    fn write(
         cfg: &Config,
         output: &mut dyn std::io::Write,
-        data: &Word,
-        fmt: &OutputFmt,
+        res: &ExecRes,
+        verbose: bool,
     ) -> FResult<()> {
+        let data = &res.out;
         let str_output = String::from_utf8_lossy(data);
         if cfg.raw {
-            match fmt {
+            match res.fmt {
                 OutputFmt::NotExpected => {}
                 _ => output.write_all(data)?,
             }
@@ -196,7 +712,7 @@ impl Context {
             if console::colors_enabled() {
                 console::set_colors_enabled(!cfg.no_color);
             }
-            match fmt {
+            match res.fmt {
                 OutputFmt::None => writeln!(output, "{}", style(str_output).white())?,
                 OutputFmt::Expected => writeln!(
                     output,
@@ -207,33 +723,123 @@ impl Context {
                 OutputFmt::NotExpected => {
                     writeln!(output, "{} {}", style("-").red(), style(str_output).red())?
                 }
+                OutputFmt::Crash => writeln!(
+                    output,
+                    "{} [{}] {}",
+                    style("!").magenta().bold(),
+                    style(res.crash.as_ref().map(|x| x.summary()).unwrap_or_default())
+                        .magenta()
+                        .bold(),
+                    style(str_output).magenta()
+                )?,
+                OutputFmt::Hang => writeln!(
+                    output,
+                    "{} [timeout] {}",
+                    style("~").yellow().bold(),
+                    style(str_output).yellow()
+                )?,
+                OutputFmt::ConnErr => writeln!(
+                    output,
+                    "{} [{}] {}",
+                    style("x").red().bold(),
+                    style(res.conn_err.map(|x| x.description()).unwrap_or_default())
+                        .red()
+                        .bold(),
+                    style(str_output).red()
+                )?,
+            }
+            if verbose || cfg.show_duration {
+                writeln!(
+                    output,
+                    "{}",
+                    style(format!("time: {:?}", res.duration)).dim()
+                )?;
+            }
+            if verbose {
+                writeln!(
+                    output,
+                    "{}",
+                    style(match res.seed {
+                        Some(seed) => format!("iteration: {} seed: {}", res.iteration, seed),
+                        None => format!("iteration: {}", res.iteration),
+                    })
+                    .dim()
+                )?;
+                if !res.substitutions.is_empty() {
+                    let words: Vec<String> = res
+                        .substitutions
+                        .iter()
+                        .map(|x| {
+                            format!(
+                                "{}[{}]={:?}",
+                                String::from_utf8_lossy(&x.target),
+                                x.index,
+                                String::from_utf8_lossy(&x.word)
+                            )
+                        })
+                        .collect();
+                    writeln!(
+                        output,
+                        "{}",
+                        style(format!("words: {}", words.join(" "))).dim()
+                    )?;
+                }
+                // the output of runners usually differs from what was sent
+                if res.input != res.out {
+                    writeln!(
+                        output,
+                        "{}",
+                        style(format!("input: {:?}", String::from_utf8_lossy(&res.input))).dim()
+                    )?;
+                }
+            }
+            if let Some(file) = &res.file {
+                writeln!(
+                    output,
+                    "{}",
+                    style(format!("file: {}", file.display())).dim()
+                )?;
+            }
+            if cfg.show_stderr && !res.err.is_empty() {
+                writeln!(
+                    output,
+                    "{}",
+                    style(String::from_utf8_lossy(&res.err).trim_end()).dim()
+                )?;
             }
         }
         Ok(())
     }
 
     /// maybe execute a runner if one was supplied, if not simply echo the data
-    fn maybe_exec(&self, data: &Word, rand: &mut Rand) -> FResult<ExecRes> {
+    fn maybe_exec(&self, data: &Word, state: &mut IterState) -> FResult<ExecRes> {
         if let Some(runner) = &self.runner {
-            runner.run_and_expect(self, data, rand)
+            runner.run_and_expect(self, data, state)
         } else {
             Ok(ExecRes {
                 exit_code: ExitCodes::Success,
                 out: data.to_owned(),
                 fmt: OutputFmt::None,
+                ..Default::default()
             })
         }
     }
 
     /// apply the next transformation
     /// and return the read bytes
-    fn apply_next(&self, input: &[u8], result: &mut Word, rand: &mut Rand) -> FResult<usize> {
+    fn apply_next(
+        &self,
+        input: &[u8],
+        primary: &Target,
+        result: &mut Word,
+        state: &mut IterState,
+    ) -> FResult<usize> {
         if input.is_empty() {
             Ok(0)
-        } else if self.target.should_replace(input) {
-            let word = &self.select_word(rand)?;
+        } else if let Some((target, len)) = self.find_target(input, primary) {
+            let word = &self.select_word(target, state)?;
             result.extend_from_slice(word);
-            Ok(self.target.len())
+            Ok(len)
         } else {
             let d = &input[0..1];
             result.extend_from_slice(d);
@@ -241,25 +847,44 @@ impl Context {
         }
     }
 
-    /// This function converts the input data
-    /// into an output which is collected into a single Word
-    /// (this can be disabled in Context's settings)
-    /// It will also streams results into output if it is provided
-    pub fn apply(&self, input: &[u8], rand: &mut Rand) -> FResult<ExecRes> {
-        debug!("Input: {:?}", input);
-
-        let mut data = &input[0..];
+    /// Replaces all targets in the input with words from their word lists.
+    /// primary replaces the default target's placeholder
+    pub fn substitute(
+        &self,
+        input: &[u8],
+        primary: &Target,
+        state: &mut IterState,
+    ) -> FResult<Word> {
+        let mut data = input;
         let mut result = Vec::new();
         while !data.is_empty() {
-            let read = self.apply_next(data, &mut result, rand)?;
+            let read = self.apply_next(data, primary, &mut result, state)?;
             if read == 0 {
                 break;
             }
 
             data = &data[read..];
         }
+        Ok(result)
+    }
+
+    /// This function converts the input data
+    /// into an output which is collected into a single Word
+    /// (this can be disabled in Context's settings)
+    /// It will also streams results into output if it is provided
+    pub fn apply(&self, input: &[u8], state: &mut IterState) -> FResult<ExecRes> {
+        debug!("Input: {:?}", input);
 
-        let exec_res = self.maybe_exec(&result, rand)?;
+        let result = self.substitute(input, &self.targets[0].target, state)?;
+        let n_input = state.substitutions.len();
+        let mut exec_res = self.maybe_exec(&result, state)?;
+        exec_res.input = result;
+        exec_res.substitutions = state.substitutions.to_owned();
+        exec_res.template_words = state.substitutions[n_input..]
+            .iter()
+            .map(|x| x.index)
+            .collect();
+        exec_res.iteration = state.iteration;
 
         debug!("Res: {:?}", exec_res);
         Ok(exec_res)
@@ -274,6 +899,31 @@ pub enum Expect {
     Equals(Word),
     ExitCode(Option<i32>),
     Len(usize),
+    Timeout,
+    ConnErr(ConnErr),
+    /// Http status code in an inclusive range
+    Status(u16, u16),
+    /// Http header with a value that matches the regex if one is supplied
+    Header(String, Option<regex::Regex>),
+    ContentLength(usize),
+    /// Parse the output as json and check the value at a path
+    Json(JsonExpect),
+    SlowerThan(Duration),
+    /// The duration deviates from the baseline by more than n sigma
+    TimeDeviation(f64, TimingBaseline),
+    /// Any of the substituted words appears in the output in one of the encodings
+    Reflection(Vec<Encoding>),
+    /// The response differs from the responses observed during calibration
+    Anomaly(ResponseBaseline),
+    /// Apply a comparator to a different output stream
+    Stream(Stream, Box<Expect>),
+    /// Compare a number of the result with a constant
+    Number(Metric, Cmp, i64),
+    /// Every comparator matches
+    All(Vec<Expect>),
+    /// At least one comparator matches
+    Any(Vec<Expect>),
+    Not(Box<Expect>),
 }
 
 impl Expect {
@@ -298,11 +948,158 @@ impl Expect {
         for contains in cfg.contains.iter() {
             expects.push(Self::Contains(contains.to_owned()));
         }
+        if cfg.expect_stream != Stream::Stdout {
+            expects = expects
+                .into_iter()
+                .map(|x| {
+                    if x.applies_to_output() {
+                        Self::Stream(cfg.expect_stream, Box::new(x))
+                    } else {
+                        x
+                    }
+                })
+                .collect();
+        }
+        if cfg.expect_timeout {
+            expects.push(Self::Timeout);
+        }
+        for conn_err in cfg.expect_conn_err.iter() {
+            expects.push(Self::ConnErr(*conn_err));
+        }
+        for status in cfg.expect_status.iter() {
+            expects.push(Self::parse_status(status)?);
+        }
+        for header in cfg.expect_header.iter() {
+            expects.push(match header.split_once(':') {
+                Some((name, re)) => Self::Header(
+                    name.trim().to_owned(),
+                    Some(regex::Regex::new(re.trim()).map_err(|_| Error::InvalidRegex)?),
+                ),
+                None => Self::Header(header.trim().to_owned(), None),
+            });
+        }
+        for len in cfg.expect_content_length.iter() {
+            expects.push(Self::ContentLength(*len));
+        }
+        for json in cfg.expect_json.iter() {
+            expects.push(Self::json(JsonExpect::parse(json)?, cfg.expect_stream));
+        }
+        if let Some(ms) = cfg.expect_slower_than {
+            expects.push(Self::SlowerThan(Duration::from_millis(ms)));
+        }
+        if cfg.expect_anomaly {
+            expects.push(Self::Anomaly(Default::default()));
+        }
+        if let Some(sigma) = cfg.expect_time_deviation {
+            expects.push(Self::TimeDeviation(
+                sigma,
+                TimingBaseline::new(cfg.timing_baseline),
+            ));
+        }
+        if cfg.expect_reflection {
+            let encodings = if cfg.reflection_encoding.is_empty() {
+                Encoding::ALL.to_vec()
+            } else {
+                cfg.reflection_encoding.to_owned()
+            };
+            expects.push(Self::Reflection(encodings));
+        }
+        for expr in cfg.expect_expr.iter() {
+            expects.push(super::expr::parse(expr)?);
+        }
         Ok(expects)
     }
 
+    /// Parse a status code (404), class (5xx) or range (500-599)
+    pub fn parse_status(status: &str) -> FResult<Self> {
+        let parse = |x: &str| x.trim().parse::<u16>().map_err(|_| Error::ArgError);
+        let status = status.trim().to_lowercase();
+        if let Some((from, to)) = status.split_once('-') {
+            Ok(Self::Status(parse(from)?, parse(to)?))
+        } else if let Some(class) = status.strip_suffix("xx") {
+            let class = parse(class)?;
+            if !(1..=5).contains(&class) {
+                return Err(Error::ArgError);
+            }
+            Ok(Self::Status(class * 100, class * 100 + 99))
+        } else {
+            let status = parse(&status)?;
+            Ok(Self::Status(status, status))
+        }
+    }
+
+    /// Json comparator for a stream. Http responses are checked by body unless
+    /// a different stream is selected
+    pub fn json(json: JsonExpect, stream: Stream) -> Self {
+        let stream = match stream {
+            Stream::Stdout => Stream::Body,
+            x => x,
+        };
+        Self::Stream(stream, Box::new(Self::Json(json)))
+    }
+
+    /// true if the comparator has to learn from a calibration phase
+    pub fn needs_calibration(&self) -> bool {
+        match self {
+            Expect::Anomaly(_) | Expect::TimeDeviation(..) => true,
+            Expect::Stream(_, expect) | Expect::Not(expect) => expect.needs_calibration(),
+            Expect::All(expects) | Expect::Any(expects) => {
+                expects.iter().any(|x| x.needs_calibration())
+            }
+            _ => false,
+        }
+    }
+
+    /// The amount of calibration runs the comparator needs
+    pub fn calibration_runs(&self) -> usize {
+        match self {
+            Expect::TimeDeviation(_, baseline) => baseline.n_sample(),
+            Expect::Stream(_, expect) | Expect::Not(expect) => expect.calibration_runs(),
+            Expect::All(expects) | Expect::Any(expects) => expects
+                .iter()
+                .map(|x| x.calibration_runs())
+                .max()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+
+    /// Learn from the results of the calibration phase
+    pub fn calibrate(&self, samples: &[RunRes]) {
+        match self {
+            Expect::Anomaly(baseline) => baseline.calibrate(samples),
+            Expect::TimeDeviation(_, baseline) => {
+                let durations: Vec<Duration> = samples.iter().map(|x| x.duration).collect();
+                baseline.calibrate(&durations);
+            }
+            Expect::Stream(_, expect) | Expect::Not(expect) => expect.calibrate(samples),
+            Expect::All(expects) | Expect::Any(expects) => {
+                expects.iter().for_each(|x| x.calibrate(samples))
+            }
+            _ => {}
+        }
+    }
+
+    /// true if the comparator looks at the output stream
+    pub(super) fn applies_to_output(&self) -> bool {
+        matches!(
+            self,
+            Expect::Contains(_)
+                | Expect::Regex(_)
+                | Expect::Equals(_)
+                | Expect::Len(_)
+                | Expect::Json(_)
+                | Expect::Reflection(_)
+        )
+    }
+
     /// compare expected data/exit code with actual data/exit code
-    pub fn expect(&self, data: &Word, exit_code: Option<i32>) -> bool {
+    pub fn expect(&self, res: &RunRes) -> bool {
+        self.expect_in(res, &res.out)
+    }
+
+    /// compare with data taken from any of the result's output streams
+    fn expect_in(&self, res: &RunRes, data: &Word) -> bool {
         match self {
             Expect::Contains(contains) => {
                 for window in data.windows(contains.len()) {
@@ -317,8 +1114,53 @@ impl Expect {
                 re.is_match(&utf8)
             }
             Expect::Equals(expected) => expected == data,
-            Expect::ExitCode(expected) => &exit_code == expected,
+            Expect::ExitCode(expected) => &res.exit_code == expected,
             Expect::Len(len) => data.len() == *len,
+            Expect::Timeout => res.timed_out,
+            Expect::ConnErr(expected) => res.conn_err == Some(*expected),
+            Expect::Status(from, to) => res
+                .http
+                .as_ref()
+                .map(|x| (*from..=*to).contains(&x.status))
+                .unwrap_or(false),
+            Expect::Header(name, re) => res
+                .http
+                .as_ref()
+                .map(|x| {
+                    x.header(name)
+                        .any(|value| re.as_ref().map(|re| re.is_match(value)).unwrap_or(true))
+                })
+                .unwrap_or(false),
+            Expect::Json(json) => json.matches(data),
+            Expect::Reflection(encodings) => {
+                is_reflected(res.substitutions.iter().map(|x| &x.word), data, encodings)
+            }
+            Expect::SlowerThan(duration) => res.duration > *duration,
+            Expect::TimeDeviation(sigma, baseline) => baseline
+                .stats()
+                .map(|x| x.deviates(res.duration, *sigma))
+                .unwrap_or(false),
+            Expect::Anomaly(baseline) => baseline.is_anomaly(res),
+            Expect::ContentLength(len) => res
+                .http
+                .as_ref()
+                .map(|x| x.content_length() == *len)
+                .unwrap_or(false),
+            Expect::Stream(stream, expect) => match stream {
+                Stream::Stdout => expect.expect_in(res, &res.out),
+                Stream::Stderr => expect.expect_in(res, &res.err),
+                Stream::Both => expect.expect_in(res, &res.out) || expect.expect_in(res, &res.err),
+                Stream::Body => {
+                    expect.expect_in(res, res.http.as_ref().map(|x| &x.body).unwrap_or(&res.out))
+                }
+            },
+            Expect::Number(metric, cmp, value) => metric
+                .value(res, data)
+                .map(|x| cmp.compare(x, *value))
+                .unwrap_or(false),
+            Expect::All(expects) => expects.iter().all(|x| x.expect_in(res, data)),
+            Expect::Any(expects) => expects.iter().any(|x| x.expect_in(res, data)),
+            Expect::Not(expect) => !expect.expect_in(res, data),
         }
     }
 }
@@ -326,22 +1168,40 @@ impl Expect {
 #[cfg(test)]
 mod test {
     use crate::core::{
-        rand::Rand,
-        runner::{default_command_expect, output_command_runner},
-        transform::{ContextIter, Expect},
+        baseline::TimingBaseline,
+        config::{Strategy, Stream},
+        corpus::{CorpusDir, CorpusMeta},
+        coverage::Coverage,
+        rand::{iteration_seed, Rand},
+        runner::{
+            default_command_expect,
+            file::{file_command_expect, file_command_runner, FileDrop, DEFAULT_FILE_NAME},
+            http::{http_command_runner, Http, HttpRes},
+            output_command_runner, shell_command_runner, temp_path, CommandRunnerKind, RunRes,
+        },
+        transform::{ContextIter, Expect, Target, TargetWords},
     };
 
-    use super::{Context, ExecRes};
+    use super::{Context, ExecRes, Substitution};
 
     fn output_ctx(input: &str, n_run: u32, expect: Option<Expect>) -> ContextIter {
         ContextIter {
             input: input.bytes().collect(),
             count: 0,
             n_run,
+            step: 1,
             rand: Rand::from_seed(1),
+            mutator: Default::default(),
+            seeds: vec![],
+            corpus: None,
+            seed: None,
             ctx: Context {
-                words: vec![b"123".to_vec(), b"45".to_vec(), b"abc".to_vec()],
-                target: Default::default(),
+                targets: vec![TargetWords::new(
+                    Default::default(),
+                    vec![b"123".to_vec(), b"45".to_vec(), b"abc".to_vec()],
+                )],
+                strategy: Default::default(),
+                slots: vec![],
                 expect: if let Some(expect) = expect {
                     vec![expect]
                 } else {
@@ -353,15 +1213,30 @@ mod test {
                     on_expect: default_command_expect,
                 }),
                 dry_run: false,
+                coverage: None,
+                server: Default::default(),
             },
         }
     }
 
     fn assert_apply(mut ctx: ContextIter, expected: Vec<ExecRes>) {
-        let res: Vec<ExecRes> = ctx.try_collect().unwrap();
+        let mut res: Vec<ExecRes> = ctx.try_collect().unwrap();
+        // durations are not reproducible and seeds are checked separately
+        res.iter_mut().for_each(|x| {
+            x.duration = Default::default();
+            x.seed = None;
+        });
         assert_eq!(expected, res);
     }
 
+    fn sub(target: &str, index: usize, word: &str) -> Substitution {
+        Substitution {
+            target: target.into(),
+            index,
+            word: word.into(),
+        }
+    }
+
     #[test]
     fn success() {
         assert_apply(
@@ -370,6 +1245,9 @@ mod test {
                 exit_code: super::ExitCodes::Success,
                 out: b"{12: abc}".to_vec(),
                 fmt: super::OutputFmt::None,
+                input: b"{12: abc}".to_vec(),
+                substitutions: vec![sub("OXIFUZZ", 2, "abc")],
+                ..Default::default()
             }],
         );
 
@@ -380,13 +1258,430 @@ mod test {
                     exit_code: super::ExitCodes::Success,
                     out: b"{12: abc}".to_vec(),
                     fmt: super::OutputFmt::Expected,
+                    input: b"{12: abc}".to_vec(),
+                    substitutions: vec![sub("OXIFUZZ", 2, "abc")],
+                    ..Default::default()
                 },
                 ExecRes {
                     exit_code: super::ExitCodes::Failure,
-                    out: b"{12: 45}".to_vec(),
+                    out: b"{12: 123}".to_vec(),
                     fmt: super::OutputFmt::NotExpected,
+                    input: b"{12: 123}".to_vec(),
+                    substitutions: vec![sub("OXIFUZZ", 0, "123")],
+                    iteration: 1,
+                    ..Default::default()
                 },
             ],
         );
     }
+
+    #[test]
+    fn worker_split() {
+        let ctx = output_ctx("OXIFUZZ", 5, None);
+        let mut iterations = vec![];
+        for worker in 0..2 {
+            let mut ctx = ctx.for_worker(worker, 2, Rand::from_seed(1)).unwrap();
+            while ctx.iteration() < 5 {
+                iterations.push(ctx.iteration());
+                ctx.next().unwrap().unwrap();
+            }
+            assert!(ctx.next().is_none());
+        }
+        assert_eq!(vec![0, 2, 4, 1, 3], iterations);
+    }
+
+    #[test]
+    fn seeded_iterations() {
+        let mut ctx = output_ctx("OXIFUZZ", 6, None);
+        ctx.seed = Some(7);
+
+        let single: Vec<ExecRes> = ctx.clone().try_collect().unwrap();
+        let mut split: Vec<ExecRes> = (0..2)
+            .flat_map(|worker| {
+                ctx.for_worker(worker, 2, Rand::from_seed(worker as u64))
+                    .unwrap()
+            })
+            .try_collect()
+            .unwrap();
+        split.sort_by_key(|x| x.iteration);
+        let without_durations = |res: &[ExecRes]| -> Vec<ExecRes> {
+            res.iter()
+                .map(|x| ExecRes {
+                    duration: Default::default(),
+                    ..x.clone()
+                })
+                .collect()
+        };
+        assert_eq!(without_durations(&single), without_durations(&split));
+
+        for (i, res) in single.iter().enumerate() {
+            assert_eq!(i as u32, res.iteration);
+            assert_eq!(Some(iteration_seed(7, i as u32)), res.seed);
+        }
+    }
+
+    #[test]
+    fn random_files() {
+        let path = std::env::temp_dir().join(format!("oxifuzz-random-{}", std::process::id()));
+        let values: Vec<u8> = [1u64, 2, 0].iter().flat_map(|x| x.to_ne_bytes()).collect();
+        std::fs::write(&path, values).unwrap();
+
+        // the words are picked by the values of the file, not by seeds derived from them
+        let mut ctx = output_ctx("OXIFUZZ", 3, None);
+        ctx.rand = Rand::from_path(&path);
+        let results: Vec<ExecRes> = ctx.try_collect().unwrap();
+        assert_eq!(
+            vec![b"45".to_vec(), b"abc".to_vec(), b"123".to_vec()],
+            results.iter().map(|x| x.out.to_owned()).collect::<Vec<_>>()
+        );
+        assert!(results.iter().all(|x| x.seed.is_none()));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn corpus_findings() {
+        let path = std::env::temp_dir().join(format!("oxifuzz-findings-{}", std::process::id()));
+        let mut ctx = output_ctx("q=OXIFUZZ", 6, Some(Expect::Contains(b"abc".to_vec())));
+        ctx.seed = Some(3);
+        ctx.corpus = Some(CorpusDir::new(&path).unwrap());
+        let results: Vec<ExecRes> = ctx.try_collect().unwrap();
+
+        let findings: Vec<&ExecRes> = results
+            .iter()
+            .filter(|x| x.fmt == super::OutputFmt::Expected)
+            .collect();
+        assert!(!findings.is_empty());
+        for res in findings {
+            let saved = path.join(CorpusDir::name(&res.input));
+            // the input that was sent is saved
+            assert_eq!(b"q=abc".to_vec(), std::fs::read(&saved).unwrap());
+            let meta =
+                CorpusMeta::parse(&std::fs::read_to_string(saved.with_extension("meta")).unwrap())
+                    .unwrap();
+            // the run's seed and the iteration reproduce the iteration's seed
+            assert_eq!(
+                Some(iteration_seed(meta.seed.unwrap(), meta.iteration)),
+                meta.iteration_seed
+            );
+        }
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn hangs() {
+        let mut ctx = output_ctx("5", 1, None);
+        ctx.ctx.runner = Some(super::CommandRunner {
+            kind: CommandRunnerKind::Shell {
+                cmd: "sleep".into(),
+                cmd_args: vec!["OXIFUZZ".into()],
+                cmd_arg_target: "OXIFUZZ".into(),
+                no_stdin: false,
+                timeout: Some(100),
+                mode: Default::default(),
+            },
+            on_run: shell_command_runner,
+            on_expect: default_command_expect,
+        });
+        ctx.ctx.targets = vec![TargetWords::new(Default::default(), vec![b"5".to_vec()])];
+
+        let res = ctx.next().unwrap().unwrap();
+        assert!(res.timed_out);
+        assert_eq!(super::ExitCodes::Hang, res.exit_code);
+        assert!(res.duration < std::time::Duration::from_secs(5));
+    }
+
+    #[test]
+    fn timing_calibration() {
+        let baseline = TimingBaseline::new(5);
+        let mut ctx = output_ctx(
+            "OXIFUZZ",
+            3,
+            Some(Expect::TimeDeviation(3.0, baseline.clone())),
+        );
+        assert!(ctx.ctx.expect[0].needs_calibration());
+        assert_eq!(5, ctx.ctx.expect[0].calibration_runs());
+
+        // fuzzed runs do not learn the baseline
+        ctx.next().unwrap().unwrap();
+        assert_eq!(None, baseline.stats());
+        ctx.calibrate(&[b"x".to_vec()]).unwrap();
+        let stats = baseline.stats().unwrap();
+        ctx.next().unwrap().unwrap();
+        assert_eq!(Some(stats), baseline.stats());
+    }
+
+    #[test]
+    fn calibration_files() {
+        let dir = temp_path("calibration");
+        std::fs::create_dir(&dir).unwrap();
+        let mut ctx = output_ctx("OXIFUZZ", 1, Some(Expect::Anomaly(Default::default())));
+        ctx.ctx.runner = Some(super::CommandRunner {
+            kind: CommandRunnerKind::File(FileDrop {
+                cmd: "cat".into(),
+                cmd_args: vec!["@@".into()],
+                cmd_arg_target: "OXIFUZZ".into(),
+                file_target: "@@".into(),
+                timeout: None,
+                dir: dir.to_owned(),
+                extension: None,
+                name: DEFAULT_FILE_NAME.into(),
+                files: Default::default(),
+            }),
+            on_run: file_command_runner,
+            on_expect: file_command_expect,
+        });
+
+        ctx.calibrate(&[b"x".to_vec()]).unwrap();
+        assert_eq!(0, std::fs::read_dir(&dir).unwrap().count());
+        // the file of the first iteration is not held by calibration
+        ctx.ctx.expect = vec![Expect::Contains(b"nothing".to_vec())];
+        assert_eq!(None, ctx.next().unwrap().unwrap().file);
+        assert_eq!(0, std::fs::read_dir(&dir).unwrap().count());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn replay() {
+        let mut ctx = output_ctx("q=OXIFUZZ", 4, Some(Expect::Contains(b"abc".to_vec())));
+        ctx.seed = Some(3);
+        let results: Vec<ExecRes> = ctx.clone().try_collect().unwrap();
+
+        for res in results.iter() {
+            let (request, replayed) = ctx.replay(res.iteration, None).unwrap();
+            assert_eq!(res.input, request);
+            assert_eq!(
+                ExecRes {
+                    duration: res.duration,
+                    ..replayed
+                },
+                *res
+            );
+            let (_, replayed) = ctx.replay(res.iteration, res.seed).unwrap();
+            assert_eq!(res.out, replayed.out);
+        }
+        ctx.ctx.coverage = Some(Coverage::new().unwrap());
+        assert!(ctx.replay(0, None).is_err());
+        ctx.ctx.coverage = None;
+        ctx.seed = None;
+        assert!(ctx.replay(0, None).is_err());
+
+        let path = std::env::temp_dir().join(format!("oxifuzz-replay-{}", std::process::id()));
+        std::fs::write(&path, b"q=OXIFUZZ-abc").unwrap();
+        std::fs::write(
+            path.with_extension("meta"),
+            "reason=coverage\niteration=7\niteration_seed=9\n",
+        )
+        .unwrap();
+        // coverage inputs are not findings
+        assert!(ctx.replay_file(&path).is_err());
+        std::fs::remove_file(path.with_extension("meta")).unwrap();
+        assert!(ctx.replay_file(&path).is_err());
+        std::fs::write(
+            path.with_extension("meta"),
+            "reason=expected\niteration=7\niteration_seed=9\n",
+        )
+        .unwrap();
+        let (request, res) = ctx.replay_file(&path).unwrap();
+        // saved inputs are sent as is
+        assert_eq!(b"q=OXIFUZZ-abc".to_vec(), request);
+        assert_eq!(super::OutputFmt::Expected, res.fmt);
+        assert_eq!((7, Some(9)), (res.iteration, res.seed));
+        std::fs::remove_file(path.with_extension("meta")).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn replay_templates() {
+        let path = std::env::temp_dir().join(format!("oxifuzz-templates-{}", std::process::id()));
+        let mut ctx = output_ctx("q=OXIFUZZ", 6, Some(Expect::Contains(b"arg=".to_vec())));
+        ctx.seed = Some(5);
+        ctx.corpus = Some(CorpusDir::new(&path).unwrap());
+        ctx.ctx.runner = Some(super::CommandRunner {
+            kind: CommandRunnerKind::Shell {
+                cmd: "echo".into(),
+                cmd_args: vec!["arg=OXIFUZZ".into()],
+                cmd_arg_target: "OXIFUZZ".into(),
+                no_stdin: false,
+                timeout: None,
+                mode: Default::default(),
+            },
+            on_run: shell_command_runner,
+            on_expect: default_command_expect,
+        });
+        let results: Vec<ExecRes> = ctx.clone().try_collect().unwrap();
+
+        let mut replayed = std::collections::HashSet::new();
+        // an input is saved by the first run that sent it
+        for res in results
+            .iter()
+            .filter(|x| replayed.insert(x.input.to_owned()))
+        {
+            let (_, replayed) = ctx
+                .replay_file(&path.join(CorpusDir::name(&res.input)))
+                .unwrap();
+            assert_eq!(res.out, replayed.out);
+            assert_eq!(res.substitutions[1..], replayed.substitutions);
+        }
+
+        std::fs::remove_dir_all(path).unwrap();
+    }
+
+    #[test]
+    fn named_targets() {
+        let mut ctx = output_ctx("USER:PASS OXIFUZZ USERNAME", 1, None);
+        ctx.ctx.targets.push(TargetWords::new(
+            Target::Word(b"USER".to_vec()),
+            vec![b"admin".to_vec()],
+        ));
+        ctx.ctx.targets.push(TargetWords::new(
+            Target::Word(b"USERNAME".to_vec()),
+            vec![b"root".to_vec()],
+        ));
+        ctx.ctx.targets.push(TargetWords::new(
+            Target::Word(b"PASS".to_vec()),
+            vec![b"hunter2".to_vec()],
+        ));
+        assert_apply(
+            ctx,
+            vec![ExecRes {
+                exit_code: super::ExitCodes::Success,
+                out: b"admin:hunter2 123 root".to_vec(),
+                fmt: super::OutputFmt::None,
+                input: b"admin:hunter2 123 root".to_vec(),
+                substitutions: vec![
+                    sub("USER", 0, "admin"),
+                    sub("PASS", 0, "hunter2"),
+                    sub("OXIFUZZ", 0, "123"),
+                    sub("USERNAME", 0, "root"),
+                ],
+                ..Default::default()
+            }],
+        );
+    }
+
+    fn enumerate(input: &str, strategy: Strategy) -> Vec<String> {
+        let mut ctx = output_ctx(input, 0, None);
+        ctx.ctx.targets.push(TargetWords::new(
+            Target::Word(b"B".to_vec()),
+            vec![b"x".to_vec(), b"y".to_vec()],
+        ));
+        ctx.ctx.strategy = strategy;
+        ctx.ctx.slots = ctx.ctx.enumerate_slots(&ctx.input).unwrap();
+        ctx.n_run = ctx.ctx.enumeration_len();
+        ctx.map(|x| String::from_utf8(x.unwrap().out).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn strategies() {
+        assert_eq!(
+            vec!["123-x", "45-y", "abc-x"],
+            enumerate("OXIFUZZ-B", Strategy::Sequential)
+        );
+        assert_eq!(
+            vec!["123-x", "45-y"],
+            enumerate("OXIFUZZ-B", Strategy::Pitchfork)
+        );
+        assert_eq!(
+            vec!["123-x", "45-x", "abc-x", "123-y", "45-y", "abc-y"],
+            enumerate("OXIFUZZ-B", Strategy::Cartesian)
+        );
+    }
+
+    #[test]
+    fn streams() {
+        let res = RunRes {
+            out: b"ok".to_vec(),
+            err: b"parse error".to_vec(),
+            ..Default::default()
+        };
+        let contains = |x: &str| Box::new(Expect::Contains(x.into()));
+
+        assert!(!Expect::Contains(b"error".to_vec()).expect(&res));
+        assert!(Expect::Stream(Stream::Stderr, contains("error")).expect(&res));
+        assert!(!Expect::Stream(Stream::Stderr, contains("ok")).expect(&res));
+        assert!(Expect::Stream(Stream::Both, contains("ok")).expect(&res));
+        assert!(Expect::Stream(Stream::Both, contains("error")).expect(&res));
+    }
+
+    #[test]
+    fn http_dry_runs() {
+        let http = |url: Option<&str>, request: Option<&[u8]>| {
+            let mut ctx = output_ctx("data", 1, None);
+            ctx.ctx.dry_run = true;
+            ctx.ctx.targets = vec![TargetWords::new(Default::default(), vec![b"\xe9".to_vec()])];
+            ctx.ctx.runner = Some(super::CommandRunner {
+                kind: CommandRunnerKind::Http(Http {
+                    url: url.map(|x| x.into()),
+                    headers: vec!["X-A: 1".into()],
+                    method: Default::default(),
+                    no_headers: false,
+                    cmd_arg_target: "OXIFUZZ".into(),
+                    request: request.map(|x| x.to_vec()),
+                    session: Default::default(),
+                    client: isahc::HttpClient::new().unwrap(),
+                }),
+                on_run: http_command_runner,
+                on_expect: default_command_expect,
+            });
+            ctx.next().unwrap().unwrap().out
+        };
+
+        assert_eq!(
+            b"http://x/a\n\nX-A: 1\n\n\ndata".to_vec(),
+            http(Some("http://x/a"), None)
+        );
+        // raw requests are substituted as bytes and get the headers of the command line
+        assert_eq!(
+            b"POST http://x/a\nHost:x\nX-A: 1\n\n\xff\x00\xe9\xfe".to_vec(),
+            http(
+                None,
+                Some(b"POST /a HTTP/1.1\r\nHost: x\r\n\r\n\xff\x00OXIFUZZ\xfe")
+            )
+        );
+    }
+
+    #[test]
+    fn http_responses() {
+        let res = RunRes {
+            out: b"503\nSet-Cookie:session=OXIFUZZ\n\n\nunavailable".to_vec(),
+            http: Some(HttpRes {
+                status: 503,
+                headers: vec![("Set-Cookie".into(), "session=OXIFUZZ".into())],
+                body: b"unavailable".to_vec(),
+            }),
+            ..Default::default()
+        };
+        let header = |name: &str, re: Option<&str>| {
+            Expect::Header(name.into(), re.map(|x| regex::Regex::new(x).unwrap()))
+        };
+
+        assert!(Expect::parse_status("5xx").unwrap().expect(&res));
+        assert!(Expect::parse_status("500-503").unwrap().expect(&res));
+        assert!(!Expect::parse_status("200").unwrap().expect(&res));
+        assert!(Expect::parse_status("abc").is_err());
+        assert!(Expect::parse_status("700xx").is_err());
+        assert!(Expect::parse_status("0xx").is_err());
+
+        assert!(header("set-cookie", None).expect(&res));
+        assert!(header("Set-Cookie", Some("OXIFUZZ")).expect(&res));
+        assert!(!header("Set-Cookie", Some("^admin")).expect(&res));
+        assert!(!header("Location", None).expect(&res));
+
+        assert!(Expect::ContentLength(11).expect(&res));
+        assert!(Expect::Stream(
+            Stream::Body,
+            Box::new(Expect::Equals(b"unavailable".to_vec()))
+        )
+        .expect(&res));
+        assert!(!Expect::Equals(b"unavailable".to_vec()).expect(&res));
+
+        // structured comparators never match other runners
+        assert!(!Expect::parse_status("5xx")
+            .unwrap()
+            .expect(&RunRes::default()));
+    }
 }